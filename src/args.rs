@@ -1,19 +1,69 @@
+use super::error::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FailurePolicy {
+    Abort,
+    Skip,
+    Continue,
+    Retry(u32),
+}
+
+impl FailurePolicy {
+    pub fn parse(value: &str) -> Result<FailurePolicy> {
+        match value {
+            "abort" => Ok(FailurePolicy::Abort),
+            "skip" => Ok(FailurePolicy::Skip),
+            "continue" => Ok(FailurePolicy::Continue),
+            _ => match value.strip_prefix("retry:").map(|n| n.parse::<u32>()) {
+                Some(Ok(retries)) if retries > 0 => Ok(FailurePolicy::Retry(retries)),
+                _ => Err(Error::InvalidArgument(format!(
+                    "invalid failure policy \"{}\"",
+                    value
+                ))),
+            },
+        }
+    }
+}
+
 pub struct Args {
     pub interval: std::time::Duration,
     pub warmup: std::time::Duration,
     pub cooldown: std::time::Duration,
-    pub jobs: Option<u32>,
+    pub strategy: super::sequence::Strategy,
+    pub jobs_spec: Option<String>,
     pub sequence: bool,
-    pub target: String,
-    pub clean_target: String,
+    pub cpus: Option<Vec<usize>>,
+    pub sweep_cpus: Option<Vec<u32>>,
+    pub samplers: Vec<String>,
+    pub repeat: u32,
+    pub on_failure: FailurePolicy,
+    pub timeout: Option<std::time::Duration>,
+    pub total_timeout: Option<std::time::Duration>,
+    /// End of the total timeout, counted from the start of bsprof.
+    pub deadline: Option<std::time::Instant>,
+    pub target: Option<String>,
+    pub clean_target: Option<String>,
+    pub build_system: super::build::BuildSystem,
+    pub trace: Option<String>,
+    pub formats: Vec<super::report::OutputFormat>,
+    pub output_dir: String,
+    pub prefix: String,
+    pub output: Option<String>,
+    pub force: bool,
+    pub log: Option<super::cmd::output::OutputCapture>,
+    pub cgroup: Option<super::profile::cgroup::CgroupSettings>,
     pub command: String,
     pub args: Vec<String>,
 }
 
 impl Args {
-    pub fn parse_from_cmdline() -> Result<Args, &'static str> {
+    pub fn parse_from_cmdline() -> Result<Args> {
         let cmd_line_args = std::env::args().collect::<Vec<String>>();
-        let cmd = clap::Command::new("Build System Profiler")
+        parse_args(Args::command(), &cmd_line_args)
+    }
+
+    fn command() -> clap::Command {
+        clap::Command::new("Build System Profiler")
             .author("Marcin Smoczyński, smoczynski.marcin@gmail.com")
             .about("A simple tool for profiling build systems")
             .version(clap::crate_version!())
@@ -38,34 +88,173 @@ impl Args {
                     .default_value("0")
                     .help("Cooldown time in ms"),
             )
-            .arg(
-                clap::Arg::new("jobs")
-                    .short('j')
-                    .long("jobs")
-                    .help("Number of jobs"),
-            )
+            .arg(clap::Arg::new("jobs").short('j').long("jobs").help(
+                "Number of jobs: N, list and ranges (1,2,8..16:step=4), pow2 or adaptive[:tol=N]",
+            ))
             .arg(
                 clap::Arg::new("sequence")
                     .short('s')
                     .long("sequence")
                     .num_args(0)
-                    .help("Profile build system with increasing number of jobs"),
+                    .help("Profile build system with increasing number of jobs up to N"),
+            )
+            .arg(
+                clap::Arg::new("repeat")
+                    .short('r')
+                    .long("repeat")
+                    .default_value("1")
+                    .help("Number of runs for each number of jobs"),
+            )
+            .arg(
+                clap::Arg::new("on_failure")
+                    .long("on-failure")
+                    .default_value("abort")
+                    .help("What to do when the build fails: abort, skip remaining runs of the number of jobs, continue or retry:N times"),
+            )
+            .arg(
+                clap::Arg::new("timeout")
+                    .long("timeout")
+                    .required(false)
+                    .help("Terminate a run after the duration, e.g. 90s, 30m or 2h, and record it as timed out"),
+            )
+            .arg(
+                clap::Arg::new("total_timeout")
+                    .long("total-timeout")
+                    .required(false)
+                    .help("Stop profiling after the duration and save the runs completed so far"),
             )
             .arg(
                 clap::Arg::new("target")
                     .short('t')
                     .long("target")
                     .required(false)
-                    .default_value("all")
-                    .help("Target to build"),
+                    .help("Target to build [default: depends on build system]"),
             )
             .arg(
                 clap::Arg::new("clean_target")
                     .short('C')
                     .long("clean-target")
                     .required(false)
-                    .default_value("clean")
-                    .help("Target to clean"),
+                    .help("Target to clean [default: depends on build system]"),
+            )
+            .arg(
+                clap::Arg::new("build_system")
+                    .short('b')
+                    .long("build-system")
+                    .required(false)
+                    .value_parser(super::build::NAMES)
+                    .help("Build system [default: detected]"),
+            )
+            .arg(
+                clap::Arg::new("jobs_arg")
+                    .long("jobs-arg")
+                    .required(false)
+                    .allow_hyphen_values(true)
+                    .help("Jobs argument template for generic build system, e.g. \"-j{}\""),
+            )
+            .arg(
+                clap::Arg::new("format")
+                    .long("format")
+                    .required(false)
+                    .default_value("json")
+                    .help("Comma separated report formats: json, csv, ndjson"),
+            )
+            .arg(
+                clap::Arg::new("output_dir")
+                    .long("output-dir")
+                    .required(false)
+                    .default_value(".")
+                    .help("Directory of the report files"),
+            )
+            .arg(
+                clap::Arg::new("prefix")
+                    .long("prefix")
+                    .required(false)
+                    .default_value("report")
+                    .help("File name prefix of the report files"),
+            )
+            .arg(
+                clap::Arg::new("output")
+                    .short('o')
+                    .long("output")
+                    .required(false)
+                    .conflicts_with_all(["output_dir", "prefix"])
+                    .help("Report path template with {date}, {host}, {git_rev} and {jobs}"),
+            )
+            .arg(
+                clap::Arg::new("force")
+                    .long("force")
+                    .num_args(0)
+                    .help("Overwrite existing report files"),
+            )
+            .arg(
+                clap::Arg::new("trace")
+                    .long("trace")
+                    .required(false)
+                    .help("Save Chrome trace of the build processes to file"),
+            )
+            .arg(
+                clap::Arg::new("log_dir")
+                    .long("log-dir")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("logs")
+                    .required(false)
+                    .help("Save timestamped build output to DIR/run-<jobs>-<run>.log [default: logs]"),
+            )
+            .arg(
+                clap::Arg::new("warning_regex")
+                    .long("warning-regex")
+                    .default_value(super::cmd::output::DEFAULT_WARNING_REGEX)
+                    .help("Regex matching warning lines in the build output"),
+            )
+            .arg(
+                clap::Arg::new("error_regex")
+                    .long("error-regex")
+                    .default_value(super::cmd::output::DEFAULT_ERROR_REGEX)
+                    .help("Regex matching error lines in the build output"),
+            )
+            .arg(
+                clap::Arg::new("cgroup")
+                    .long("cgroup")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("")
+                    .required(false)
+                    .help("Run the build in a transient cgroup v2 under PARENT [default: own cgroup]"),
+            )
+            .arg(
+                clap::Arg::new("cgroup_cpu_max")
+                    .long("cgroup-cpu-max")
+                    .required(false)
+                    .requires("cgroup")
+                    .help("CPU bandwidth limit of the cgroup in CPUs, e.g. 2.5"),
+            )
+            .arg(
+                clap::Arg::new("memory_limit")
+                    .long("memory-limit")
+                    .alias("cgroup-memory-max")
+                    .required(false)
+                    .help("Limit the build memory, e.g. 16G, runs the build in a cgroup"),
+            )
+            .arg(
+                clap::Arg::new("cpus")
+                    .long("cpus")
+                    .required(false)
+                    .help("Pin the build to the CPU list, e.g. 0-7"),
+            )
+            .arg(
+                clap::Arg::new("sweep_cpus")
+                    .long("sweep-cpus")
+                    .required(false)
+                    .help("Profile for several simulated core counts: list, ranges or pow2"),
+            )
+            .arg(
+                clap::Arg::new("samplers")
+                    .long("samplers")
+                    .required(false)
+                    .default_value("cpu,mem,io,psi,procs")
+                    .help("Comma separated samplers of the data points: cpu, mem, io, psi, procs"),
             )
             .arg(
                 clap::Arg::new("command")
@@ -76,94 +265,369 @@ impl Args {
                 clap::Arg::new("args")
                     .num_args(0..)
                     .help("Additional build arguments"),
-            );
-        parse_args(cmd, &cmd_line_args)
+            )
     }
 
     pub fn print(&self) {
         println!("Profiling command:   {}", self.command);
+        println!("Build system:        {}", self.build_system.name());
         println!("Profiling args:      {:?}", self.args);
         println!("Profiling warmup:    {} ms", self.warmup.as_millis());
         println!("Profiling cooldown:  {} ms", self.cooldown.as_millis());
-        println!(
-            "Profiling sequence:  {}",
-            if self.sequence { "yes" } else { "no" }
-        );
-        if let Some(jobs) = self.jobs {
-            println!("Profiling jobs:      {}", jobs);
+        println!("Profiling jobs:      {}", self.strategy.describe());
+        if let Some(cpus) = &self.cpus {
+            println!(
+                "CPUs:                {}",
+                super::profile::affinity::format_cpu_list(cpus)
+            );
+        }
+        if let Some(sweep) = &self.sweep_cpus {
+            println!("Sweep CPUs:          {:?}", sweep);
+        }
+        println!("Profiling repeat:    {}", self.repeat);
+        println!("On failure:          {:?}", self.on_failure);
+        if let Some(timeout) = self.timeout {
+            println!("Run timeout:         {} s", timeout.as_secs_f64());
+        }
+        if let Some(timeout) = self.total_timeout {
+            println!("Total timeout:       {} s", timeout.as_secs_f64());
         }
         println!("Profiling interval:  {} ms", self.interval.as_millis());
+        println!("Samplers:            {}", self.samplers.join(","));
+        match &self.output {
+            Some(output) => println!("Report output:       {} ({:?})", output, self.formats),
+            None => println!(
+                "Report output:       {} ({:?})",
+                self.report_base(),
+                self.formats
+            ),
+        }
+        if let Some(trace) = &self.trace {
+            println!("Trace output:        {}", trace);
+        }
+        if let Some(log) = &self.log {
+            println!("Log directory:       {}", log.dir);
+        }
+        if let Some(cgroup) = &self.cgroup {
+            println!(
+                "Cgroup parent:       {}",
+                cgroup.parent.as_deref().unwrap_or("own cgroup")
+            );
+            if let Some(cpus) = cgroup.cpu_max {
+                println!("Cgroup CPU limit:    {} CPUs", cpus);
+            }
+            if let Some(memory) = cgroup.memory_max {
+                println!("Cgroup memory limit: {} MB", memory / 1024 / 1024);
+            }
+        }
+    }
+
+    /// Strategy for a build running on `max` CPUs, used when sweeping the
+    /// number of CPUs.
+    pub fn strategy_for(&self, max: u32) -> Result<super::sequence::Strategy> {
+        super::sequence::Strategy::parse(self.jobs_spec.as_deref(), self.sequence, max)
     }
 
-    pub fn create_sequence(&self) -> Vec<u32> {
-        let jobs = self.jobs.unwrap_or(super::profile::info::get_cpu_count());
-        if self.sequence {
-            (1..=jobs).collect()
-        } else {
-            vec![jobs]
+    /// Path of the report files without the extension when no output
+    /// template is given.
+    pub fn report_base(&self) -> String {
+        if self.output_dir == "." {
+            return self.prefix.clone();
+        }
+        std::path::Path::new(&self.output_dir)
+            .join(&self.prefix)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// CPUs used by the build, all allowed CPUs when not pinned.
+    pub fn available_cpus(&self) -> Vec<usize> {
+        match &self.cpus {
+            Some(cpus) => cpus.clone(),
+            None => super::profile::affinity::allowed_cpus(),
         }
     }
 }
 
-fn parse_args(cmd: clap::Command, cmd_line_args: &Vec<String>) -> Result<Args, &'static str> {
+fn parse_ms(m: &clap::ArgMatches, id: &str, name: &str) -> Result<u32> {
+    let value = m.get_one::<String>(id).unwrap();
+    value
+        .parse::<u32>()
+        .map_err(|_| Error::InvalidArgument(format!("invalid {} value \"{}\"", name, value)))
+}
+
+/// Parses a size in bytes with an optional binary K, M, G or T suffix.
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, shift) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 30),
+        Some((i, 'T' | 't')) => (&value[..i], 40),
+        _ => (value, 0),
+    };
+    match number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+    {
+        Some(size) if size > 0 => Ok(size),
+        _ => Err(Error::InvalidArgument(format!(
+            "invalid size \"{}\"",
+            value
+        ))),
+    }
+}
+
+/// Parses a positive duration in seconds with an optional ms, s, m or h
+/// suffix.
+pub fn parse_duration(value: &str) -> Result<std::time::Duration> {
+    let value = value.trim();
+    let (number, scale) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 1e-3)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60.0)
+    } else if let Some(number) = value.strip_suffix('h') {
+        (number, 3600.0)
+    } else {
+        (value, 1.0)
+    };
+    match number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| std::time::Duration::try_from_secs_f64(n * scale).ok())
+    {
+        Some(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(Error::InvalidArgument(format!(
+            "invalid duration \"{}\"",
+            value
+        ))),
+    }
+}
+
+fn parse_args(cmd: clap::Command, cmd_line_args: &Vec<String>) -> Result<Args> {
     let m = cmd.get_matches_from(cmd_line_args);
 
-    let interval = m
-        .get_one::<String>("interval_ms")
-        .unwrap()
-        .parse::<u32>()
-        .unwrap();
+    let interval = parse_ms(&m, "interval_ms", "interval")?;
+    if interval == 0 {
+        return Err(Error::invalid_argument("interval must be positive"));
+    }
 
     let command = match m.get_one::<String>("command") {
         Some(cmd) => cmd.to_owned(),
         None => {
-            return Err("No command specified");
+            return Err(Error::invalid_argument("no command specified"));
         }
     };
 
-    let warmup = m
-        .get_one::<String>("warmup_ms")
-        .unwrap()
-        .parse::<u32>()
-        .unwrap();
-
-    let cooldown = m
-        .get_one::<String>("cooldown_ms")
-        .unwrap()
-        .parse::<u32>()
-        .unwrap();
-
-    let mut jobs = None;
-    if let Some(jobs_option) = m.get_one::<String>("jobs") {
-        let jobs_parsed = jobs_option.parse::<u32>();
-        if jobs_parsed.is_err() || jobs_parsed.clone().unwrap() == 0 {
-            return Err("Invalid jobs value");
-        }
-        jobs = Some(jobs_parsed.unwrap());
-    }
+    let warmup = parse_ms(&m, "warmup_ms", "warmup")?;
+    let cooldown = parse_ms(&m, "cooldown_ms", "cooldown")?;
 
     let mut sequence = false;
     if let Some(sequnce_option) = m.get_one::<bool>("sequence") {
         sequence = *sequnce_option;
     }
 
+    let samplers = match m.get_one::<String>("samplers") {
+        Some(list) => super::profile::sampler::parse_names(list)?,
+        None => Vec::new(),
+    };
+
+    let allowed_cpus = super::profile::affinity::allowed_cpus();
+    let cpus = match m.get_one::<String>("cpus") {
+        Some(list) => {
+            let cpus = super::profile::affinity::parse_cpu_list(list)?;
+            if !allowed_cpus.is_empty() && cpus.iter().any(|cpu| !allowed_cpus.contains(cpu)) {
+                return Err(Error::InvalidArgument(format!(
+                    "CPU list \"{}\" is not a subset of the available CPUs {}",
+                    list,
+                    super::profile::affinity::format_cpu_list(&allowed_cpus)
+                )));
+            }
+            Some(cpus)
+        }
+        None => None,
+    };
+    let num_cpus = match &cpus {
+        Some(cpus) => cpus.len() as u32,
+        None if allowed_cpus.is_empty() => super::profile::info::get_cpu_count(),
+        None => allowed_cpus.len() as u32,
+    };
+
+    let jobs_spec = m.get_one::<String>("jobs").cloned();
+    let strategy = super::sequence::Strategy::parse(jobs_spec.as_deref(), sequence, num_cpus)?;
+
+    let sweep_cpus = match m.get_one::<String>("sweep_cpus") {
+        Some(spec) => match super::sequence::Strategy::parse(Some(spec), false, num_cpus)? {
+            super::sequence::Strategy::Fixed(counts) if counts.iter().all(|n| *n <= num_cpus) => {
+                Some(counts)
+            }
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "invalid CPU sweep \"{}\", at most {} CPUs are available",
+                    spec, num_cpus
+                )))
+            }
+        },
+        None => None,
+    };
+
+    let repeat = match m.get_one::<String>("repeat").unwrap().parse::<u32>() {
+        Ok(repeat) if repeat > 0 => repeat,
+        _ => return Err(Error::invalid_argument("invalid repeat value")),
+    };
+
+    let on_failure = FailurePolicy::parse(m.get_one::<String>("on_failure").unwrap())?;
+    let timeout = match m.get_one::<String>("timeout") {
+        Some(timeout) => Some(parse_duration(timeout)?),
+        None => None,
+    };
+    let total_timeout = match m.get_one::<String>("total_timeout") {
+        Some(timeout) => Some(parse_duration(timeout)?),
+        None => None,
+    };
+
     let cmdargs: Vec<String> = match m.get_many::<String>("args") {
         Some(args) => args.map(|x| x.to_owned()).collect(),
         None => Vec::new(),
     };
 
-    let target = m.get_one::<String>("target").unwrap().to_owned();
-    let clean_target = m.get_one::<String>("clean_target").unwrap().to_owned();
+    let target = m.get_one::<String>("target").cloned();
+    let clean_target = m.get_one::<String>("clean_target").cloned();
+
+    let jobs_arg = m.get_one::<String>("jobs_arg");
+    let build_system = match m.get_one::<String>("build_system") {
+        Some(name) => {
+            if jobs_arg.is_some() && name != "generic" {
+                return Err(Error::invalid_argument(
+                    "jobs argument template requires generic build system",
+                ));
+            }
+            super::build::BuildSystem::from_name(name, jobs_arg)?
+        }
+        None => match jobs_arg {
+            Some(_) => super::build::BuildSystem::from_name("generic", jobs_arg)?,
+            None => super::build::BuildSystem::detect(
+                &command,
+                &std::env::current_dir().unwrap_or_default(),
+            ),
+        },
+    };
+    let trace = m.get_one::<String>("trace").cloned();
+    let formats = super::report::OutputFormat::parse_list(m.get_one::<String>("format").unwrap())?;
+    let output_dir = m.get_one::<String>("output_dir").unwrap().to_owned();
+    let prefix = m.get_one::<String>("prefix").unwrap().to_owned();
+    let output = m.get_one::<String>("output").cloned();
+    let force = m.get_one::<bool>("force").copied().unwrap_or(false);
+    if prefix.is_empty() || prefix.contains('/') {
+        return Err(Error::InvalidArgument(format!(
+            "invalid report prefix \"{}\"",
+            prefix
+        )));
+    }
+    let log = match m.get_one::<String>("log_dir") {
+        Some(dir) => Some(super::cmd::output::OutputCapture::new(
+            dir,
+            m.get_one::<String>("warning_regex").unwrap(),
+            m.get_one::<String>("error_regex").unwrap(),
+        )?),
+        None => None,
+    };
+
+    let memory_limit = match m.get_one::<String>("memory_limit") {
+        Some(memory) => Some(parse_size(memory)?),
+        None => None,
+    };
+    let cgroup_parent = match (m.get_one::<String>("cgroup"), memory_limit) {
+        (Some(parent), _) => Some(parent.as_str()),
+        (None, Some(_)) => Some(""),
+        (None, None) => None,
+    };
+    let cgroup = match cgroup_parent {
+        Some(parent) => {
+            let cpu_max = match m.get_one::<String>("cgroup_cpu_max") {
+                Some(cpus) => match cpus.parse::<f64>() {
+                    Ok(cpus) if cpus > 0.0 => Some(cpus),
+                    _ => return Err(Error::invalid_argument("invalid cgroup CPU limit")),
+                },
+                None => None,
+            };
+            Some(super::profile::cgroup::CgroupSettings {
+                parent: Some(parent.to_owned()).filter(|p| !p.is_empty()),
+                cpu_max,
+                memory_max: memory_limit,
+            })
+        }
+        None => None,
+    };
 
     Ok(Args {
         interval: std::time::Duration::from_millis(interval as u64),
         warmup: std::time::Duration::from_millis(warmup as u64),
         cooldown: std::time::Duration::from_millis(cooldown as u64),
-        target: target,
-        clean_target: clean_target,
-        sequence: sequence,
-        jobs: jobs,
-        command: command,
+        target,
+        clean_target,
+        build_system,
+        trace,
+        formats,
+        output_dir,
+        prefix,
+        output,
+        force,
+        log,
+        cgroup,
+        strategy,
+        jobs_spec,
+        sequence,
+        cpus,
+        sweep_cpus,
+        samplers,
+        repeat,
+        on_failure,
+        timeout,
+        total_timeout,
+        deadline: total_timeout.map(|timeout| std::time::Instant::now() + timeout),
+        command,
         args: cmdargs,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4K").unwrap(), 4096);
+        assert_eq!(parse_size("16G").unwrap(), 16 << 30);
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("1.5G").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        use std::time::Duration;
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("-1").is_err());
+        assert!(parse_duration("1d").is_err());
+    }
+
+    #[test]
+    fn test_jobs_arg() {
+        let cmd_line_args: Vec<String> = ["bsprof", "-b", "generic", "--jobs-arg", "-j{}", "make"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let args = parse_args(Args::command(), &cmd_line_args).unwrap();
+        assert_eq!(args.build_system.jobs_args(4), vec!["-j4".to_string()]);
+        assert_eq!(args.command, "make");
+    }
+}
@@ -0,0 +1,262 @@
+//! Build system adapters describing how to pass the job count, targets and
+//! how to clean the build for a particular tool.
+
+use super::cmd::Command;
+use super::error::{Error, Result};
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildSystem {
+    Make,
+    Ninja,
+    CMake,
+    Cargo,
+    Meson,
+    Bazel,
+    Gradle,
+    Generic(String),
+}
+
+pub const NAMES: [&str; 8] = [
+    "make", "ninja", "cmake", "cargo", "meson", "bazel", "gradle", "generic",
+];
+
+pub const DEFAULT_JOBS_TEMPLATE: &str = "-j {}";
+
+impl BuildSystem {
+    pub fn from_name(name: &str, jobs_template: Option<&String>) -> Result<BuildSystem> {
+        match name {
+            "make" => Ok(BuildSystem::Make),
+            "ninja" => Ok(BuildSystem::Ninja),
+            "cmake" => Ok(BuildSystem::CMake),
+            "cargo" => Ok(BuildSystem::Cargo),
+            "meson" => Ok(BuildSystem::Meson),
+            "bazel" => Ok(BuildSystem::Bazel),
+            "gradle" => Ok(BuildSystem::Gradle),
+            "generic" => Ok(BuildSystem::Generic(
+                jobs_template.map_or(DEFAULT_JOBS_TEMPLATE.to_string(), |t| t.to_owned()),
+            )),
+            _ => Err(Error::InvalidArgument(format!(
+                "unknown build system \"{}\"",
+                name
+            ))),
+        }
+    }
+
+    /// Guesses the build system from the command name first, and from the
+    /// files present in the working directory when the command is a wrapper.
+    pub fn detect(command: &str, dir: &Path) -> BuildSystem {
+        let program = Path::new(command)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(command);
+
+        match program {
+            "make" | "gmake" => return BuildSystem::Make,
+            "ninja" => return BuildSystem::Ninja,
+            "cmake" => return BuildSystem::CMake,
+            "cargo" => return BuildSystem::Cargo,
+            "meson" => return BuildSystem::Meson,
+            "bazel" | "bazelisk" => return BuildSystem::Bazel,
+            "gradle" | "gradlew" => return BuildSystem::Gradle,
+            _ => {}
+        }
+
+        let markers: [(&[&str], BuildSystem); 6] = [
+            (&["build.ninja"], BuildSystem::Ninja),
+            (&["CMakeCache.txt"], BuildSystem::CMake),
+            (&["Cargo.toml"], BuildSystem::Cargo),
+            (
+                &["WORKSPACE", "WORKSPACE.bazel", "MODULE.bazel"],
+                BuildSystem::Bazel,
+            ),
+            (&["build.gradle", "build.gradle.kts"], BuildSystem::Gradle),
+            (&["Makefile", "makefile", "GNUmakefile"], BuildSystem::Make),
+        ];
+        for (files, system) in markers {
+            if files.iter().any(|file| dir.join(file).exists()) {
+                return system;
+            }
+        }
+
+        BuildSystem::Generic(DEFAULT_JOBS_TEMPLATE.to_string())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildSystem::Make => "make",
+            BuildSystem::Ninja => "ninja",
+            BuildSystem::CMake => "cmake",
+            BuildSystem::Cargo => "cargo",
+            BuildSystem::Meson => "meson",
+            BuildSystem::Bazel => "bazel",
+            BuildSystem::Gradle => "gradle",
+            BuildSystem::Generic(_) => "generic",
+        }
+    }
+
+    pub fn jobs_args(&self, jobs: u32) -> Vec<String> {
+        match self {
+            BuildSystem::Make | BuildSystem::Ninja | BuildSystem::Meson => {
+                vec!["-j".to_string(), jobs.to_string()]
+            }
+            BuildSystem::CMake => vec!["--parallel".to_string(), jobs.to_string()],
+            BuildSystem::Cargo => vec!["--jobs".to_string(), jobs.to_string()],
+            BuildSystem::Bazel => vec![format!("--local_cpu_resources={}", jobs)],
+            BuildSystem::Gradle => vec![format!("--max-workers={}", jobs)],
+            BuildSystem::Generic(template) => template
+                .split_whitespace()
+                .map(|arg| arg.replace("{}", &jobs.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn target_args(&self, target: &str) -> Vec<String> {
+        match self {
+            BuildSystem::CMake => vec!["--target".to_string(), target.to_string()],
+            BuildSystem::Cargo => vec!["--package".to_string(), target.to_string()],
+            _ => vec![target.to_string()],
+        }
+    }
+
+    pub fn default_target(&self) -> Option<&'static str> {
+        match self {
+            BuildSystem::Make => Some("all"),
+            _ => None,
+        }
+    }
+
+    /// Arguments used when none are given, e.g. the `build` subcommand.
+    fn default_args(&self) -> &'static [&'static str] {
+        match self {
+            BuildSystem::CMake => &["--build", "."],
+            BuildSystem::Cargo | BuildSystem::Bazel => &["build"],
+            BuildSystem::Meson => &["compile"],
+            _ => &[],
+        }
+    }
+
+    fn base_args(&self, args: &Vec<String>) -> Vec<String> {
+        if args.is_empty() {
+            self.default_args()
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect()
+        } else {
+            args.to_owned()
+        }
+    }
+
+    pub fn build_command(
+        &self,
+        command: &String,
+        args: &Vec<String>,
+        target: Option<&String>,
+    ) -> Command {
+        let mut cmd = Command::new(command, &self.base_args(args));
+        if let Some(target) = target.map(|t| t.as_str()).or(self.default_target()) {
+            cmd.add_args(&self.target_args(target));
+        }
+        cmd
+    }
+
+    /// Returns the command cleaning the build, `None` if the build system has
+    /// no notion of cleaning and no clean target was given.
+    pub fn clean_command(
+        &self,
+        command: &String,
+        args: &Vec<String>,
+        clean_target: Option<&String>,
+    ) -> Option<Command> {
+        let base_args = self.base_args(args);
+        if let Some(clean_target) = clean_target {
+            let mut cmd = Command::new(command, &base_args);
+            cmd.add_args(&self.target_args(clean_target));
+            return Some(cmd);
+        }
+
+        let (base_args, clean_args): (Vec<String>, &[&str]) = match self {
+            BuildSystem::Make => (base_args, &["clean"]),
+            BuildSystem::Ninja => (base_args, &["-t", "clean"]),
+            BuildSystem::CMake => (base_args, &["--target", "clean"]),
+            BuildSystem::Meson => (base_args, &["--clean"]),
+            BuildSystem::Cargo | BuildSystem::Bazel | BuildSystem::Gradle => {
+                (Vec::new(), &["clean"])
+            }
+            BuildSystem::Generic(_) => return None,
+        };
+        let mut cmd = Command::new(command, &base_args);
+        cmd.add_args(
+            &clean_args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>(),
+        );
+        Some(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_jobs_args() {
+        assert_eq!(BuildSystem::Make.jobs_args(4), strings(&["-j", "4"]));
+        assert_eq!(
+            BuildSystem::CMake.jobs_args(4),
+            strings(&["--parallel", "4"])
+        );
+        assert_eq!(
+            BuildSystem::Bazel.jobs_args(4),
+            strings(&["--local_cpu_resources=4"])
+        );
+        assert_eq!(
+            BuildSystem::Gradle.jobs_args(4),
+            strings(&["--max-workers=4"])
+        );
+        assert_eq!(
+            BuildSystem::Generic("-j{} --load {}".to_string()).jobs_args(4),
+            strings(&["-j4", "--load", "4"])
+        );
+    }
+
+    #[test]
+    fn test_detect_from_command() {
+        let dir = Path::new("/nonexistent");
+
+        assert_eq!(BuildSystem::detect("/usr/bin/make", dir), BuildSystem::Make);
+        assert_eq!(BuildSystem::detect("./gradlew", dir), BuildSystem::Gradle);
+        assert_eq!(
+            BuildSystem::detect("./build.sh", dir),
+            BuildSystem::Generic(DEFAULT_JOBS_TEMPLATE.to_string())
+        );
+    }
+
+    #[test]
+    fn test_clean_command() {
+        let args = strings(&["--build", "out"]);
+        let clean = BuildSystem::CMake
+            .clean_command(&"cmake".to_string(), &args, None)
+            .unwrap();
+        assert_eq!(
+            clean.args,
+            strings(&["--build", "out", "--target", "clean"])
+        );
+
+        let args = strings(&["build", "--release"]);
+        let clean = BuildSystem::Cargo
+            .clean_command(&"cargo".to_string(), &args, None)
+            .unwrap();
+        assert_eq!(clean.args, strings(&["clean"]));
+
+        let generic = BuildSystem::Generic(DEFAULT_JOBS_TEMPLATE.to_string());
+        assert!(generic
+            .clean_command(&"./build.sh".to_string(), &args, None)
+            .is_none());
+    }
+}
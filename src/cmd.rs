@@ -1,3 +1,26 @@
+use super::error::{Error, Result};
+use super::profile::affinity;
+use super::profile::rusage::{self, Rusage};
+use super::signal;
+use std::os::unix::io::RawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+
+pub mod output;
+
+/// How the child process is set up between fork and exec.
+#[derive(Default)]
+pub struct SpawnOptions {
+    /// Pipe stdout and stderr to the parent.
+    pub piped: bool,
+    /// Open `cgroup.procs` of the cgroup the child moves itself into.
+    pub cgroup_procs: Option<RawFd>,
+    /// CPUs the child is pinned to.
+    pub cpus: Option<Vec<usize>>,
+    /// Start the child in a new process group led by itself, so the whole
+    /// process tree can be signaled.
+    pub process_group: bool,
+}
+
 #[derive(Clone)]
 pub struct Command {
     pub name: String,
@@ -12,23 +35,112 @@ impl Command {
         }
     }
 
-    pub fn add_jobs(&mut self, jobs: u32) {
-        self.args.push("-j".to_string());
-        self.args.push(jobs.to_string());
+    pub fn add_args(&mut self, args: &[String]) {
+        self.args.extend(args.iter().cloned());
+    }
+
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.name)
+            .chain(self.args.iter())
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn spawn_with(&self, options: &SpawnOptions) -> Result<std::process::Child> {
+        let mut command = std::process::Command::new(&self.name);
+        command.args(&self.args);
+        if options.piped {
+            command
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped());
+        }
+        if options.process_group {
+            command.process_group(0);
+        }
+        if let Some(fd) = options.cgroup_procs {
+            // only async-signal-safe calls are allowed between fork and exec
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        if let Some(cpus) = &options.cpus {
+            let set = affinity::cpu_set(cpus);
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) < 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        command.spawn().map_err(|source| Error::Spawn {
+            command: self.command_line(),
+            source,
+        })
+    }
+
+    /// Reaps the child with `wait4`, returning its exit status together with
+    /// the resource usage of its whole process tree.
+    pub fn wait_rusage(
+        &self,
+        child: &mut std::process::Child,
+    ) -> Result<(std::process::ExitStatus, Rusage)> {
+        let (status, rusage) = rusage::wait4(child.id()).map_err(|source| Error::Wait {
+            command: self.command_line(),
+            source,
+        })?;
+        Ok((std::process::ExitStatus::from_raw(status), rusage))
     }
 
-    pub fn add_arg(&mut self, arg: &String) {
-        self.args.push(arg.to_owned());
+    pub fn wait(&self, child: &mut std::process::Child) -> Result<Rusage> {
+        let (status, rusage) = self.wait_rusage(child)?;
+        if !status.success() {
+            return Err(Error::ExitStatus {
+                command: self.command_line(),
+                status: status.to_string(),
+            });
+        }
+        Ok(rusage)
     }
 
-    pub fn run(&self) -> Result<(), &'static str> {
-        let mut cmd_process = std::process::Command::new(&self.name)
-            .args(&self.args)
-            .spawn()
-            .unwrap();
-        match cmd_process.wait() {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Error running command"),
+    /// Runs the command to completion, interrupted by SIGINT or SIGTERM like
+    /// the profiled build.
+    pub fn run(&self) -> Result<Rusage> {
+        let mut cmd_process = self.spawn_with(&SpawnOptions {
+            process_group: true,
+            ..Default::default()
+        })?;
+        let watch = signal::GroupWatch::start(cmd_process.id(), None);
+        let result = self.wait(&mut cmd_process);
+        match watch.stop(cmd_process.id()) {
+            Some(signal::Stop::Signal(signal)) => Err(Error::Interrupted(signal)),
+            _ => result,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_error() {
+        let cmd = Command::new(&"true".to_string(), &Vec::new());
+        let mut child = cmd.spawn_with(&SpawnOptions::default()).unwrap();
+        child.wait().unwrap();
+
+        // the child was already reaped
+        let error = cmd.wait_rusage(&mut child).unwrap_err();
+        assert!(matches!(error, Error::Wait { .. }));
+        assert!(error.to_string().starts_with("failed to wait for \"true\""));
+        assert_eq!(error.exit_code(), 5);
+    }
+}
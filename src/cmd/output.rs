@@ -0,0 +1,193 @@
+//! Capturing of the build output to timestamped log files.
+
+use crate::error::{Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+pub const DEFAULT_WARNING_REGEX: &str = r"(?i)\bwarning\b:";
+pub const DEFAULT_ERROR_REGEX: &str = r"(?i)\berror\b:";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Diagnostic {
+    pub elapsed: f64,
+    pub line: u64,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunLog {
+    pub path: String,
+    pub lines: u64,
+    pub warnings: u64,
+    pub errors: u64,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Clone)]
+pub struct OutputCapture {
+    pub dir: String,
+    pub warning: Regex,
+    pub error: Regex,
+}
+
+struct Line {
+    elapsed: f64,
+    stderr: bool,
+    text: String,
+}
+
+impl OutputCapture {
+    pub fn new(dir: &str, warning: &str, error: &str) -> Result<OutputCapture> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|e| {
+                Error::InvalidArgument(format!("invalid regex \"{}\": {}", pattern, e))
+            })
+        };
+
+        Ok(OutputCapture {
+            dir: dir.to_string(),
+            warning: compile(warning)?,
+            error: compile(error)?,
+        })
+    }
+
+    pub fn log_path(&self, name: &str) -> String {
+        std::path::Path::new(&self.dir)
+            .join(format!("{}.log", name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Tees the piped stdout and stderr of `child` to the console and to the
+    /// log file, prefixing every line with the time elapsed since `start`.
+    pub fn tee(
+        &self,
+        child: &mut std::process::Child,
+        path: &str,
+        start: Instant,
+    ) -> Result<JoinHandle<RunLog>> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| Error::io(&self.dir, e))?;
+        let mut file =
+            std::io::BufWriter::new(std::fs::File::create(path).map_err(|e| Error::io(path, e))?);
+
+        let (tx, rx) = channel::<Line>();
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(read_lines(stdout, false, start, tx.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(read_lines(stderr, true, start, tx.clone()));
+        }
+        drop(tx);
+
+        let capture = self.clone();
+        let path = path.to_string();
+        Ok(std::thread::spawn(move || {
+            let mut log = RunLog {
+                path,
+                lines: 0,
+                warnings: 0,
+                errors: 0,
+                diagnostics: Vec::new(),
+            };
+
+            for line in rx {
+                log.lines += 1;
+                let _ = writeln!(
+                    file,
+                    "[{:>10.3} {}] {}",
+                    line.elapsed,
+                    if line.stderr { "err" } else { "out" },
+                    line.text
+                );
+
+                let kind = if capture.error.is_match(&line.text) {
+                    log.errors += 1;
+                    DiagnosticKind::Error
+                } else if capture.warning.is_match(&line.text) {
+                    log.warnings += 1;
+                    DiagnosticKind::Warning
+                } else {
+                    continue;
+                };
+                log.diagnostics.push(Diagnostic {
+                    elapsed: line.elapsed,
+                    line: log.lines,
+                    kind,
+                });
+            }
+
+            for reader in readers {
+                let _ = reader.join();
+            }
+            let _ = file.flush();
+            log
+        }))
+    }
+}
+
+fn read_lines(
+    stream: impl Read + Send + 'static,
+    stderr: bool,
+    start: Instant,
+    tx: Sender<Line>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut buffer = Vec::new();
+
+        while let Ok(read) = reader.read_until(b'\n', &mut buffer) {
+            if read == 0 {
+                break;
+            }
+            // echo the output unchanged
+            let _ = if stderr {
+                std::io::stderr().write_all(&buffer)
+            } else {
+                std::io::stdout().write_all(&buffer)
+            };
+
+            let text = String::from_utf8_lossy(&buffer)
+                .trim_end_matches(['\n', '\r'])
+                .to_string();
+            let line = Line {
+                elapsed: start.elapsed().as_secs_f64(),
+                stderr,
+                text,
+            };
+            buffer.clear();
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_regexes() {
+        let capture =
+            OutputCapture::new("logs", DEFAULT_WARNING_REGEX, DEFAULT_ERROR_REGEX).unwrap();
+
+        assert!(capture
+            .warning
+            .is_match("main.c:1:2: warning: unused variable"));
+        assert!(capture.error.is_match("main.c:3:4: error: expected ';'"));
+        assert!(!capture.error.is_match("compiling error_handler.c"));
+        assert!(OutputCapture::new("logs", "(", DEFAULT_ERROR_REGEX).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Comparison of two reports, flagging regressions between runs.
+
+use super::error::{Error, Result};
+use super::profile::{info::SystemInfo, ProfileResult};
+use super::report::Report;
+
+pub struct CompareArgs {
+    pub old: String,
+    pub new: String,
+    pub threshold: f64,
+}
+
+impl CompareArgs {
+    pub fn parse_from(cmd_line_args: &[String]) -> Result<CompareArgs> {
+        let cmd = clap::Command::new("compare")
+            .about("Compare two reports and flag regressions")
+            .arg(clap::Arg::new("old").required(true).help("Baseline report"))
+            .arg(
+                clap::Arg::new("new")
+                    .required(true)
+                    .help("Report to compare"),
+            )
+            .arg(
+                clap::Arg::new("threshold")
+                    .short('t')
+                    .long("threshold")
+                    .default_value("5")
+                    .help("Regression threshold in percent"),
+            );
+        let m = cmd.get_matches_from(cmd_line_args);
+
+        let threshold = match m.get_one::<String>("threshold").unwrap().parse::<f64>() {
+            Ok(threshold) if threshold >= 0.0 => threshold,
+            _ => return Err(Error::invalid_argument("invalid threshold value")),
+        };
+
+        Ok(CompareArgs {
+            old: m.get_one::<String>("old").unwrap().to_owned(),
+            new: m.get_one::<String>("new").unwrap().to_owned(),
+            threshold,
+        })
+    }
+}
+
+struct Metrics {
+    elapsed_time: f64,
+    user_time: f64,
+    system_time: f64,
+    max_rss: f64,
+    peak_memory: f64,
+}
+
+fn mean(results: &[&ProfileResult], f: impl Fn(&ProfileResult) -> f64) -> f64 {
+    results.iter().map(|r| f(r)).sum::<f64>() / results.len().max(1) as f64
+}
+
+impl Metrics {
+    /// Averages metrics over all runs with the given number of jobs.
+    fn from_results(results: &[&ProfileResult]) -> Metrics {
+        Metrics {
+            elapsed_time: mean(results, |r| r.elapsed_time),
+            user_time: mean(results, |r| r.rusage.user_time.as_secs_f64()),
+            system_time: mean(results, |r| r.rusage.system_time.as_secs_f64()),
+            max_rss: mean(results, |r| r.rusage.max_rss as f64),
+            peak_memory: mean(results, |r| {
+                r.datapoints
+                    .iter()
+                    .filter_map(|d| d.mem())
+                    .map(|m| m.used)
+                    .max()
+                    .unwrap_or(0) as f64
+            }),
+        }
+    }
+
+    fn rows(&self) -> [(&'static str, &'static str, f64); 5] {
+        [
+            ("elapsed", "s", self.elapsed_time),
+            ("user", "s", self.user_time),
+            ("system", "s", self.system_time),
+            ("max rss", "MB", self.max_rss / 1024.0),
+            ("peak memory", "MB", self.peak_memory / 1024.0 / 1024.0),
+        ]
+    }
+}
+
+fn system_info_differences(old: &SystemInfo, new: &SystemInfo) -> Vec<String> {
+    let mut differences = Vec::new();
+    if old.os != new.os {
+        differences.push(format!("OS: {} -> {}", old.os, new.os));
+    }
+    if old.cpu_name != new.cpu_name {
+        differences.push(format!("CPU: {} -> {}", old.cpu_name, new.cpu_name));
+    }
+    if old.num_cpus != new.num_cpus {
+        differences.push(format!("CPUs: {} -> {}", old.num_cpus, new.num_cpus));
+    }
+    if old.total_memory != new.total_memory {
+        differences.push(format!(
+            "memory: {} MB -> {} MB",
+            old.total_memory / 1024 / 1024,
+            new.total_memory / 1024 / 1024
+        ));
+    }
+    differences
+}
+
+fn delta_percent(old: f64, new: f64) -> f64 {
+    if old == 0.0 {
+        if new == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        (new - old) / old * 100.0
+    }
+}
+
+fn jobs_results(report: &Report, jobs: u32) -> Vec<&ProfileResult> {
+    report
+        .profile_results
+        .iter()
+        .filter(|r| r.jobs == jobs && r.status.is_success())
+        .collect()
+}
+
+/// Prints per-jobs deltas, returns the number of regressions found.
+pub fn compare(old: &Report, new: &Report, threshold: f64) -> u32 {
+    if old.system_info != new.system_info {
+        println!("Warning: reports were collected on different systems");
+        for difference in system_info_differences(&old.system_info, &new.system_info) {
+            println!("  {}", difference);
+        }
+    }
+
+    let mut jobs: Vec<u32> = Vec::new();
+    for result in &old.profile_results {
+        if !jobs.contains(&result.jobs) {
+            jobs.push(result.jobs);
+        }
+    }
+    let mut regressions = 0;
+
+    for j in jobs {
+        // job counts without a successful run are not compared, failed runs
+        // would average to zeros
+        let old_results = jobs_results(old, j);
+        if old_results.is_empty() {
+            println!(
+                "Jobs {}: no successful run in old report, not comparable",
+                j
+            );
+            continue;
+        }
+        let new_results = jobs_results(new, j);
+        if new_results.is_empty() {
+            if new.profile_results.iter().any(|r| r.jobs == j) {
+                println!(
+                    "Jobs {}: no successful run in new report, not comparable",
+                    j
+                );
+            } else {
+                println!("Jobs {}: missing in new report", j);
+            }
+            continue;
+        }
+        let old_metrics = Metrics::from_results(&old_results);
+        let new_metrics = Metrics::from_results(&new_results);
+
+        println!("Jobs {}:", j);
+        for ((name, unit, old_value), (_, _, new_value)) in
+            old_metrics.rows().into_iter().zip(new_metrics.rows())
+        {
+            let delta = delta_percent(old_value, new_value);
+            let regression = delta > threshold;
+            if regression {
+                regressions += 1;
+            }
+            println!(
+                "  {:<12} {:>12.3} {:<2} -> {:>12.3} {:<2} {:>+8.1}%{}",
+                name,
+                old_value,
+                unit,
+                new_value,
+                unit,
+                delta,
+                if regression { "  REGRESSION" } else { "" }
+            );
+        }
+    }
+
+    regressions
+}
+
+pub fn run(args: &CompareArgs) -> Result<u32> {
+    let old = Report::load(&args.old)?;
+    let new = Report::load(&args.new)?;
+    Ok(compare(&old, &new, args.threshold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{ProfileSettings, RunStatus};
+    use std::time::Duration;
+
+    fn report(results: &[(u32, RunStatus, f64)]) -> Report {
+        let settings = ProfileSettings::new(Duration::from_secs(1), Duration::ZERO, Duration::ZERO);
+        let mut report = Report::new(&crate::profile::info::get_system_info(), &settings);
+        for (jobs, status, elapsed_time) in results {
+            report.add_result(ProfileResult {
+                elapsed_time: *elapsed_time,
+                jobs: *jobs,
+                status: *status,
+                ..Default::default()
+            });
+        }
+        report
+    }
+
+    #[test]
+    fn test_delta_percent() {
+        assert_eq!(delta_percent(10.0, 11.0), 10.0);
+        assert_eq!(delta_percent(10.0, 5.0), -50.0);
+        assert_eq!(delta_percent(0.0, 0.0), 0.0);
+        assert_eq!(delta_percent(0.0, 1.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_failed_jobs_not_compared() {
+        let failed = RunStatus::Failed { code: 2 };
+        let old = report(&[(1, RunStatus::Success, 1.0), (2, failed, 0.1)]);
+        let new = report(&[(1, RunStatus::Success, 1.0), (2, RunStatus::Success, 0.6)]);
+
+        assert_eq!(compare(&old, &new, 5.0), 0);
+        assert_eq!(compare(&new, &old, 5.0), 0);
+    }
+}
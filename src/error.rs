@@ -0,0 +1,97 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    ExitStatus {
+        command: String,
+        status: String,
+    },
+    InvalidArgument(String),
+    /// Reaping a spawned command failed.
+    Wait {
+        command: String,
+        source: std::io::Error,
+    },
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    Serialization(serde_json::Error),
+    /// Profiling stopped by SIGINT or SIGTERM.
+    Interrupted(i32),
+    /// The total time allowed for profiling ran out.
+    Timeout(std::time::Duration),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn invalid_argument(message: &str) -> Error {
+        Error::InvalidArgument(message.to_string())
+    }
+
+    pub fn io(path: &str, source: std::io::Error) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// Process exit code reported for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidArgument(_) => 2,
+            Error::Spawn { .. } => 3,
+            Error::ExitStatus { .. } => 4,
+            Error::Wait { .. } | Error::Io { .. } => 5,
+            Error::Serialization(_) => 6,
+            Error::Interrupted(signal) => 128 + signal,
+            Error::Timeout(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Spawn { command, source } => {
+                write!(f, "failed to spawn \"{}\": {}", command, source)
+            }
+            Error::ExitStatus { command, status } => {
+                write!(f, "\"{}\" failed: {}", command, status)
+            }
+            Error::InvalidArgument(message) => write!(f, "invalid argument: {}", message),
+            Error::Wait { command, source } => {
+                write!(f, "failed to wait for \"{}\": {}", command, source)
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Serialization(source) => write!(f, "serialization error: {}", source),
+            Error::Interrupted(signal) => write!(f, "interrupted by signal {}", signal),
+            Error::Timeout(limit) => {
+                write!(f, "total timeout of {} s exceeded", limit.as_secs_f64())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn { source, .. } | Error::Wait { source, .. } | Error::Io { source, .. } => {
+                Some(source)
+            }
+            Error::Serialization(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        Error::Serialization(error)
+    }
+}
@@ -1,37 +1,447 @@
 mod args;
+mod build;
 mod cmd;
+mod compare;
+mod error;
+mod ninja;
 mod profile;
 mod report;
+mod sequence;
+mod signal;
+mod stats;
 
 fn main() {
-    let args = args::Args::parse_from_cmdline().unwrap();
+    let cmd_line_args = std::env::args().collect::<Vec<String>>();
+    let result = match cmd_line_args.get(1).map(String::as_str) {
+        Some("compare") => run_compare(&cmd_line_args[1..]),
+        Some("report") => run_report(&cmd_line_args[1..]),
+        _ => run_profile(),
+    };
+
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+fn run_compare(cmd_line_args: &[String]) -> error::Result<i32> {
+    let compare_args = compare::CompareArgs::parse_from(cmd_line_args)?;
+    let regressions = compare::run(&compare_args)?;
+    if regressions > 0 {
+        println!("Found {} regressions", regressions);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+fn run_report(cmd_line_args: &[String]) -> error::Result<i32> {
+    let report_args = report::cli::ReportArgs::parse_from(cmd_line_args)?;
+    report::cli::run(&report_args)?;
+    Ok(0)
+}
+
+fn run_profile() -> error::Result<i32> {
+    let args = args::Args::parse_from_cmdline()?;
 
     args.print();
+    signal::install();
+
+    let host_info = profile::info::get_system_info();
+    let metadata = report::metadata::Metadata::collect();
+
+    // one report per simulated CPU count when sweeping
+    let mut reports = Vec::new();
+    match &args.sweep_cpus {
+        Some(counts) => {
+            let available_cpus = args.available_cpus();
+            for &count in counts {
+                let cpus = available_cpus
+                    .get(..count as usize)
+                    .ok_or_else(|| error::Error::invalid_argument("not enough CPUs to sweep"))?
+                    .to_vec();
+                reports.push((
+                    Some(count),
+                    args.strategy_for(count)?,
+                    Some(cpus),
+                    format!("-cpus-{}", count),
+                ));
+            }
+        }
+        None => reports.push((
+            None,
+            args.strategy.clone(),
+            args.cpus.clone(),
+            String::new(),
+        )),
+    }
 
-    let sys_info = profile::info::get_system_info();
+    let mut outputs = Vec::new();
+    for (_, strategy, _, suffix) in &reports {
+        let base = match &args.output {
+            Some(template) => {
+                let path = metadata.expand(template, &strategy.label())?;
+                path.strip_suffix(".json")
+                    .map(str::to_string)
+                    .unwrap_or(path)
+            }
+            None => args.report_base(),
+        };
+        let trace = args.trace.as_ref().map(|trace| with_suffix(trace, suffix));
+        outputs.push((format!("{}{}", base, suffix), trace));
+    }
+    check_outputs(&args, &outputs)?;
+
+    // without a clean step every run after the first is an incremental build
+    let several_runs = args.repeat > 1
+        || reports.len() > 1
+        || reports.iter().any(|(_, strategy, _, _)| {
+            !matches!(strategy, sequence::Strategy::Fixed(jobs) if jobs.len() == 1)
+        });
+    let clean_command =
+        args.build_system
+            .clean_command(&args.command, &args.args, args.clean_target.as_ref());
+    if several_runs && clean_command.is_none() {
+        eprintln!(
+            "Warning: the {} build system has no clean step, runs after the first may rebuild nothing, pass --clean-target",
+            args.build_system.name()
+        );
+    }
+
+    for ((count, strategy, cpus, _), (base, trace)) in reports.into_iter().zip(outputs) {
+        if let Some(count) = count {
+            println!("Simulating {} CPUs", count);
+        }
+        profile_report(&args, &host_info, &metadata, &strategy, cpus, &base, trace)?;
+    }
+
+    Ok(0)
+}
+
+/// Refuses to overwrite existing report files unless forced, before any
+/// time is spent profiling.
+fn check_outputs(args: &args::Args, outputs: &[(String, Option<String>)]) -> error::Result<()> {
+    if args.force {
+        return Ok(());
+    }
+    for (base, trace) in outputs {
+        let paths = args
+            .formats
+            .iter()
+            .flat_map(|format| format.paths(base))
+            .chain(trace.clone());
+        for path in paths {
+            if std::path::Path::new(&path).exists() {
+                return Err(error::Error::InvalidArgument(format!(
+                    "{} already exists, use --force to overwrite it",
+                    path
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Inserts `suffix` before the extension of `path`.
+fn with_suffix(path: &str, suffix: &str) -> String {
+    let file_start = path.rfind('/').map_or(0, |i| i + 1);
+    match path[file_start..].rfind('.') {
+        Some(i) if i > 0 => format!(
+            "{}{}{}",
+            &path[..file_start + i],
+            suffix,
+            &path[file_start + i..]
+        ),
+        _ => format!("{}{}", path, suffix),
+    }
+}
+
+/// Profiles the whole jobs sequence of a build pinned to `cpus` and saves
+/// the report files at `base`, even partial ones when profiling fails.
+fn profile_report(
+    args: &args::Args,
+    host_info: &profile::info::SystemInfo,
+    metadata: &report::metadata::Metadata,
+    strategy: &sequence::Strategy,
+    cpus: Option<Vec<usize>>,
+    base: &str,
+    trace: Option<String>,
+) -> error::Result<()> {
+    let memory_limit = args.cgroup.as_ref().and_then(|c| c.memory_max);
+    let sys_info = host_info.restrict(cpus.as_deref(), memory_limit);
     sys_info.print();
 
-    let sequence = args.create_sequence();
-    println!("Profiling with {:?} jobs sequence", sequence);
+    let mut sequence = strategy.sequencer();
 
-    let settings = profile::ProfileSettings::new(args.interval, args.warmup, args.cooldown);
+    let mut settings = profile::ProfileSettings::new(args.interval, args.warmup, args.cooldown);
+    settings.cgroup = args.cgroup.clone();
+    settings.cpus = cpus;
+    settings.samplers = args.samplers.clone();
+    settings.timeout = args.timeout;
+    settings.total_timeout = args.total_timeout;
+    settings.deadline = args.deadline;
     let mut report = report::Report::new(&sys_info, &settings);
-    let clean_command = cmd::Command::new(&args.command, &vec![args.clean_target.clone()]);
-    let mut build_cmd = cmd::Command::new(&args.command, &args.args);
-    build_cmd.add_arg(&args.target);
+    report.metadata = Some(metadata.clone());
+
+    if let Some(dir) = std::path::Path::new(base).parent() {
+        std::fs::create_dir_all(dir).map_err(|e| error::Error::io(&dir.to_string_lossy(), e))?;
+    }
+    let stream = if args.formats.contains(&report::OutputFormat::Ndjson) {
+        let path = format!("{}.ndjson", base);
+        println!("Streaming report to {}", path);
+        Some(report::stream::StreamWriter::create(&path, &report)?)
+    } else {
+        None
+    };
+
+    let runner = Runner {
+        args,
+        settings: &settings,
+        clean_command: args.build_system.clean_command(
+            &args.command,
+            &args.args,
+            args.clean_target.as_ref(),
+        ),
+        build_cmd: args
+            .build_system
+            .build_command(&args.command, &args.args, args.target.as_ref()),
+        stream,
+    };
+    let status = profile_sequence(&runner, &mut sequence, &mut report);
+
+    report.sequence = Some(sequence.info());
+    if let Some(metadata) = report.metadata.as_mut() {
+        metadata.finish();
+    }
+    report.summarize();
+    report.print_summary();
+
+    match &status {
+        Ok(()) => println!("Done"),
+        Err(e) => {
+            match e {
+                error::Error::Interrupted(_) => println!("Interrupted, saving partial report"),
+                _ => println!("Profiling failed, saving partial report"),
+            }
+            report.failure = Some(e.to_string());
+        }
+    }
+    if let Some(stream) = &runner.stream {
+        stream.end(&report)?;
+    }
+    save_report(&report, &args.formats, base)?;
+
+    if let Some(trace) = &trace {
+        println!("Saving trace to {}", trace);
+        report.save_trace(trace)?;
+    }
+
+    status
+}
+
+fn save_report(
+    report: &report::Report,
+    formats: &[report::OutputFormat],
+    base: &str,
+) -> error::Result<()> {
+    for format in formats {
+        match format {
+            report::OutputFormat::Json => {
+                let path = format!("{}.json", base);
+                println!("Saving report to {}", path);
+                report.save(&path)?;
+            }
+            report::OutputFormat::Csv => {
+                for path in report.save_csv(base)? {
+                    println!("Saved CSV to {}", path);
+                }
+            }
+            // written while profiling
+            report::OutputFormat::Ndjson => {}
+        }
+    }
+    Ok(())
+}
+
+/// Commands and outputs shared by all runs of a sequence.
+struct Runner<'a> {
+    args: &'a args::Args,
+    settings: &'a profile::ProfileSettings,
+    clean_command: Option<cmd::Command>,
+    build_cmd: cmd::Command,
+    stream: Option<report::stream::StreamWriter>,
+}
+
+/// Runs the clean step before a run, returning its resource usage.
+fn clean_build(runner: &Runner) -> error::Result<Option<profile::rusage::Rusage>> {
+    if let Some(signal) = signal::received() {
+        return Err(error::Error::Interrupted(signal));
+    }
+    runner.settings.check_deadline()?;
+    match &runner.clean_command {
+        Some(clean_command) => {
+            println!("Cleaning up");
+            Ok(Some(clean_command.run()?))
+        }
+        None => Ok(None),
+    }
+}
+
+fn profile_run(
+    runner: &Runner,
+    jobs: u32,
+    run: u32,
+    retry: u32,
+    run_id: usize,
+    clean_rusage: Option<profile::rusage::Rusage>,
+) -> error::Result<profile::ProfileResult> {
+    let args = runner.args;
+    if args.repeat > 1 {
+        println!(
+            "Profiling with {} jobs, run {}/{}",
+            jobs,
+            run + 1,
+            args.repeat
+        );
+    } else {
+        println!("Profiling with {} jobs", jobs);
+    }
+
+    let log = args.log.as_ref().map(|capture| {
+        let name = match retry {
+            0 => format!("run-{}-{}", jobs, run),
+            _ => format!("run-{}-{}-retry{}", jobs, run, retry),
+        };
+        (capture, capture.log_path(&name))
+    });
+    let sink = runner
+        .stream
+        .clone()
+        .map(|stream| -> profile::DatapointSink {
+            Box::new(move |datapoint| {
+                if let Err(e) = stream.datapoint(run_id, datapoint) {
+                    eprintln!("Warning: {}", e);
+                }
+            })
+        });
+    let mut result = profile::profile(
+        &runner.build_cmd,
+        &args.build_system,
+        runner.settings,
+        jobs,
+        log,
+        sink,
+    )?;
+    result.clean_rusage = clean_rusage;
+    result.run = run;
+    if let Some(cgroup) = &result.cgroup {
+        println!(
+            "Cgroup: cpu {:.2} s, peak memory {} MB, read {} MB, written {} MB",
+            cgroup.usage_usec.unwrap_or(0) as f64 / 1e6,
+            cgroup.memory_peak.unwrap_or(0) / 1024 / 1024,
+            cgroup.io_read_bytes.unwrap_or(0) / 1024 / 1024,
+            cgroup.io_write_bytes.unwrap_or(0) / 1024 / 1024
+        );
+    }
+    if let Some(io) = &result.io_check {
+        if io.rusage_read_bytes + io.rusage_write_bytes > 0 {
+            println!(
+                "Build I/O: read {} MB, written {} MB, {:.0}% observed by sampling",
+                io.rusage_read_bytes / 1024 / 1024,
+                io.rusage_write_bytes / 1024 / 1024,
+                io.coverage() * 100.0
+            );
+        }
+    }
+    if let Some(sampling) = &result.sampling {
+        println!(
+            "Sampling: {} samples, overhead {:.2} ms ({:.1}% of interval), jitter mean {:.2} ms max {:.2} ms, {} missed",
+            sampling.samples,
+            sampling.overhead.mean * 1e3,
+            sampling.load(),
+            sampling.jitter.mean * 1e3,
+            sampling.jitter.max * 1e3,
+            sampling.missed
+        );
+    }
+    if let Some(log) = &result.log {
+        println!(
+            "Build output saved to {} ({} warnings, {} errors)",
+            log.path, log.warnings, log.errors
+        );
+    }
+    if let Some(critical_path) = result.ninja.as_ref().and_then(|n| n.critical_path.as_ref()) {
+        println!(
+            "Critical path: {:.2} s ({:.1}% of elapsed time), available parallelism {:.2}",
+            critical_path.time,
+            critical_path.elapsed_ratio * 100.0,
+            critical_path.parallelism
+        );
+    }
+    Ok(result)
+}
 
-    for j in sequence {
-        println!("Cleaning up");
-        clean_command.run().unwrap();
+fn profile_sequence(
+    runner: &Runner,
+    sequence: &mut sequence::Sequencer,
+    report: &mut report::Report,
+) -> error::Result<()> {
+    let args = runner.args;
 
-        println!("Profiling with {} jobs", j);
+    while let Some(j) = sequence.next(&report.summaries) {
+        'runs: for run in 0..args.repeat {
+            let mut retries = 0;
+            loop {
+                // a failed clean step is a failed run without a result
+                let (command, status) = match clean_build(runner) {
+                    Ok(clean_rusage) => {
+                        let run_id = report.profile_results.len();
+                        let result = profile_run(runner, j, run, retries, run_id, clean_rusage)?;
+                        if let Some(stream) = &runner.stream {
+                            stream.result(run_id, &result)?;
+                        }
+                        let status = result.status;
+                        let command = std::iter::once(&result.cmd_name)
+                            .chain(result.cmd_args.iter())
+                            .cloned()
+                            .collect::<Vec<String>>()
+                            .join(" ");
+                        report.add_result(result);
+                        if status.is_success() {
+                            break;
+                        }
+                        if let profile::RunStatus::Interrupted { signal } = status {
+                            return Err(error::Error::Interrupted(signal));
+                        }
+                        runner.settings.check_deadline()?;
 
-        let last_rusage = profile::rusage::get_process_rusage();
-        let mut result = profile::profile(&build_cmd, &settings, j);
-        result.rusage = result.rusage - last_rusage;
-        report.add_result(result);
+                        println!("Build with {} jobs failed: {}", j, status);
+                        (command, status.to_string())
+                    }
+                    Err(error::Error::ExitStatus { command, status }) => {
+                        println!(
+                            "Cleaning up before the build with {} jobs failed: {}",
+                            j, status
+                        );
+                        (command, status)
+                    }
+                    Err(e) => return Err(e),
+                };
+                match args.on_failure {
+                    args::FailurePolicy::Skip => break 'runs,
+                    args::FailurePolicy::Continue => break,
+                    args::FailurePolicy::Retry(max) if retries < max => {
+                        retries += 1;
+                        println!("Retrying ({}/{})", retries, max);
+                    }
+                    _ => return Err(error::Error::ExitStatus { command, status }),
+                }
+            }
+        }
+        report.summarize();
     }
 
-    println!("Done, saving report");
-    report.save();
+    Ok(())
 }
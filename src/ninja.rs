@@ -0,0 +1,438 @@
+//! Ninja build log ingestion and critical path analysis.
+
+use super::cmd::Command;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NinjaTarget {
+    pub output: String,
+    pub start: f64,
+    pub end: f64,
+    pub duration: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CriticalPath {
+    pub targets: Vec<String>,
+    pub time: f64,
+    pub elapsed_ratio: f64,
+    pub parallelism: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NinjaRun {
+    pub targets: Vec<NinjaTarget>,
+    pub total_time: f64,
+    pub critical_path: Option<CriticalPath>,
+}
+
+/// Ninja invocation details needed to locate its log and query its graph.
+pub struct NinjaBuild {
+    name: String,
+    tool_args: Vec<String>,
+    targets: Vec<String>,
+    log_path: PathBuf,
+    log_offset: u64,
+}
+
+// ninja options which take a value
+const VALUE_OPTIONS: [&str; 8] = ["-C", "-f", "-j", "-k", "-l", "-d", "-t", "-w"];
+
+impl NinjaBuild {
+    pub fn detect(cmd: &Command) -> Option<NinjaBuild> {
+        if !is_ninja(&cmd.name) {
+            return None;
+        }
+
+        let mut dir = PathBuf::from(".");
+        let mut build_file = PathBuf::from("build.ninja");
+        let mut tool_args = Vec::new();
+        let mut targets = Vec::new();
+        let mut args = cmd.args.iter();
+
+        while let Some(arg) = args.next() {
+            let (option, value) = match VALUE_OPTIONS.iter().find(|o| arg.starts_with(*o)) {
+                Some(option) if arg.len() > 2 => (*option, Some(arg[2..].to_string())),
+                Some(option) => (*option, args.next().cloned()),
+                None => {
+                    if !arg.starts_with('-') {
+                        targets.push(arg.to_owned());
+                    }
+                    continue;
+                }
+            };
+            let value = value?;
+            match option {
+                "-C" => dir = dir.join(&value),
+                "-f" => build_file = PathBuf::from(&value),
+                _ => continue,
+            }
+            tool_args.push(option.to_string());
+            tool_args.push(value);
+        }
+
+        let build_dir = read_builddir(&dir.join(&build_file)).unwrap_or_default();
+        let log_path = dir.join(build_dir).join(".ninja_log");
+        let log_offset = std::fs::metadata(&log_path).map_or(0, |m| m.len());
+
+        Some(NinjaBuild {
+            name: cmd.name.clone(),
+            tool_args,
+            targets,
+            log_path,
+            log_offset,
+        })
+    }
+
+    pub fn analyze(&self, elapsed_time: f64) -> Option<NinjaRun> {
+        let content = std::fs::read(&self.log_path).ok()?;
+        let targets = if (content.len() as u64) > self.log_offset {
+            parse_log(&String::from_utf8_lossy(
+                &content[self.log_offset as usize..],
+            ))
+        } else {
+            // the log was recompacted, fall back to its last run
+            last_run(parse_log(&String::from_utf8_lossy(&content)))
+        };
+        let total_time = targets.iter().map(|t| t.duration).sum();
+
+        let mut deps = match self.tool(&["graph"]) {
+            Some(graph) => parse_graph(&graph),
+            None => HashMap::new(),
+        };
+        if let Some(discovered) = self.tool(&["deps"]) {
+            for (output, inputs) in parse_deps(&discovered) {
+                deps.entry(output).or_default().extend(inputs);
+            }
+        }
+
+        let critical_path = if deps.is_empty() {
+            None
+        } else {
+            let (time, path) = critical_path(&targets, &deps);
+            Some(CriticalPath {
+                targets: path,
+                time,
+                elapsed_ratio: if elapsed_time > 0.0 {
+                    time / elapsed_time
+                } else {
+                    0.0
+                },
+                parallelism: if time > 0.0 { total_time / time } else { 0.0 },
+            })
+        };
+
+        Some(NinjaRun {
+            targets,
+            total_time,
+            critical_path,
+        })
+    }
+
+    fn tool(&self, tool: &[&str]) -> Option<String> {
+        let output = std::process::Command::new(&self.name)
+            .args(&self.tool_args)
+            .arg("-t")
+            .args(tool)
+            .args(&self.targets)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+fn is_ninja(name: &str) -> bool {
+    Path::new(name)
+        .file_name()
+        .is_some_and(|file_name| file_name == "ninja")
+}
+
+fn read_builddir(build_file: &Path) -> Option<PathBuf> {
+    let content = std::fs::read_to_string(build_file).ok()?;
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("builddir"))
+        .filter_map(|rest| rest.trim_start().strip_prefix('='))
+        .map(|value| PathBuf::from(value.trim()))
+        .next()
+}
+
+fn parse_log(content: &str) -> Vec<NinjaTarget> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            let start = fields[0].parse::<f64>().ok()? / 1000.0;
+            let end = fields[1].parse::<f64>().ok()? / 1000.0;
+            Some(NinjaTarget {
+                output: fields[3].to_string(),
+                start,
+                end,
+                duration: end - start,
+            })
+        })
+        .collect()
+}
+
+/// Entries are appended in completion order, so a drop in end time marks the
+/// beginning of a new run.
+fn last_run(targets: Vec<NinjaTarget>) -> Vec<NinjaTarget> {
+    let start = targets
+        .windows(2)
+        .rposition(|w| w[1].end < w[0].end)
+        .map_or(0, |i| i + 1);
+    targets[start..].to_vec()
+}
+
+/// Parses `ninja -t graph` output into a map of file to its direct inputs.
+fn parse_graph(dot: &str) -> HashMap<String, Vec<String>> {
+    let mut labels = HashMap::new();
+    let mut edge_nodes = HashSet::new();
+    let mut arrows = Vec::new();
+
+    for line in dot.lines() {
+        let mut quoted = line.split('"').skip(1).step_by(2);
+        let first = match quoted.next() {
+            Some(first) => first,
+            None => continue,
+        };
+        if line.contains("->") {
+            if let Some(second) = quoted.next() {
+                arrows.push((first, second));
+            }
+        } else if let Some(label) = line.split("label=\"").nth(1) {
+            let label = label.split('"').next().unwrap_or_default();
+            if line.contains("shape=ellipse") {
+                edge_nodes.insert(first);
+            } else {
+                labels.insert(first, label.to_string());
+            }
+        }
+    }
+
+    let mut edge_inputs: HashMap<&str, Vec<String>> = HashMap::new();
+    for (from, to) in &arrows {
+        if edge_nodes.contains(to) {
+            if let Some(input) = labels.get(from) {
+                edge_inputs.entry(to).or_default().push(input.clone());
+            }
+        }
+    }
+
+    let mut deps: HashMap<String, Vec<String>> = HashMap::new();
+    for (from, to) in &arrows {
+        let output = match labels.get(to) {
+            Some(output) => output.clone(),
+            None => continue,
+        };
+        let inputs = if edge_nodes.contains(from) {
+            edge_inputs.get(from).cloned().unwrap_or_default()
+        } else {
+            labels.get(from).cloned().into_iter().collect()
+        };
+        deps.entry(output).or_default().extend(inputs);
+    }
+
+    deps
+}
+
+/// Parses `ninja -t deps` output into a map of target to discovered inputs.
+fn parse_deps(content: &str) -> HashMap<String, Vec<String>> {
+    let mut deps: HashMap<String, Vec<String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            current = None;
+        } else if line.starts_with(char::is_whitespace) {
+            if let Some(target) = &current {
+                deps.entry(target.clone())
+                    .or_default()
+                    .push(line.trim().to_string());
+            }
+        } else if let Some((target, _)) = line.split_once(": #deps") {
+            current = Some(target.to_string());
+        }
+    }
+
+    deps
+}
+
+/// Finds the longest chain of built targets, weighted by their durations.
+fn critical_path(
+    targets: &[NinjaTarget],
+    deps: &HashMap<String, Vec<String>>,
+) -> (f64, Vec<String>) {
+    let durations: HashMap<&str, f64> = targets
+        .iter()
+        .map(|t| (t.output.as_str(), t.duration))
+        .collect();
+    let mut memo: HashMap<String, (f64, Option<String>)> = HashMap::new();
+
+    let mut best: Option<&str> = None;
+    let mut best_time = 0.0;
+    for target in targets {
+        let time = longest_path(
+            &target.output,
+            deps,
+            &durations,
+            &mut memo,
+            &mut HashSet::new(),
+        );
+        if best.is_none() || time > best_time {
+            best = Some(&target.output);
+            best_time = time;
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut node = best.map(|b| b.to_string());
+    while let Some(current) = node {
+        if durations.contains_key(current.as_str()) {
+            path.push(current.clone());
+        }
+        node = memo.get(&current).and_then(|(_, next)| next.clone());
+    }
+    path.reverse();
+
+    (best_time, path)
+}
+
+fn longest_path(
+    node: &str,
+    deps: &HashMap<String, Vec<String>>,
+    durations: &HashMap<&str, f64>,
+    memo: &mut HashMap<String, (f64, Option<String>)>,
+    visiting: &mut HashSet<String>,
+) -> f64 {
+    if let Some((time, _)) = memo.get(node) {
+        return *time;
+    }
+    if !visiting.insert(node.to_string()) {
+        return 0.0;
+    }
+
+    let mut longest = 0.0;
+    let mut next = None;
+    for input in deps.get(node).into_iter().flatten() {
+        let time = longest_path(input, deps, durations, memo, visiting);
+        if time > longest {
+            longest = time;
+            next = Some(input.clone());
+        }
+    }
+
+    visiting.remove(node);
+    let time = longest + durations.get(node).copied().unwrap_or(0.0);
+    memo.insert(node.to_string(), (time, next));
+    time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(output: &str, start: f64, end: f64) -> NinjaTarget {
+        NinjaTarget {
+            output: output.to_string(),
+            start,
+            end,
+            duration: end - start,
+        }
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let targets = parse_log(
+            "# ninja log v5\n\
+             0\t1500\t1700000000000000000\tmain.o\tdeadbeef\n\
+             1500\t2000\t1700000000000000000\tapp\tcafebabe\n",
+        );
+
+        assert_eq!(
+            targets,
+            vec![target("main.o", 0.0, 1.5), target("app", 1.5, 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_last_run() {
+        let targets = vec![
+            target("a.o", 0.0, 1.0),
+            target("app", 1.0, 2.0),
+            target("a.o", 0.0, 0.5),
+            target("app", 0.5, 0.7),
+        ];
+
+        assert_eq!(
+            last_run(targets),
+            vec![target("a.o", 0.0, 0.5), target("app", 0.5, 0.7)]
+        );
+    }
+
+    #[test]
+    fn test_parse_graph() {
+        let deps = parse_graph(
+            "digraph ninja {\n\
+             \"0x1\" [label=\"app\"]\n\
+             \"0x2\" [label=\"link\", shape=ellipse]\n\
+             \"0x2\" -> \"0x1\"\n\
+             \"0x3\" -> \"0x2\" [arrowhead=none]\n\
+             \"0x4\" -> \"0x2\" [arrowhead=none]\n\
+             \"0x3\" [label=\"a.o\"]\n\
+             \"0x5\" -> \"0x3\" [label=\" cc\"]\n\
+             \"0x5\" [label=\"a.c\"]\n\
+             \"0x4\" [label=\"b.o\"]\n\
+             }\n",
+        );
+
+        assert_eq!(deps["app"], vec!["a.o".to_string(), "b.o".to_string()]);
+        assert_eq!(deps["a.o"], vec!["a.c".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_deps() {
+        let deps = parse_deps(
+            "a.o: #deps 2, deps mtime 1 (VALID)\n    a.c\n    gen.h\n\n\
+             b.o: #deps 1, deps mtime 1 (VALID)\n    b.c\n\n",
+        );
+
+        assert_eq!(deps["a.o"], vec!["a.c".to_string(), "gen.h".to_string()]);
+        assert_eq!(deps["b.o"], vec!["b.c".to_string()]);
+    }
+
+    #[test]
+    fn test_critical_path() {
+        let targets = vec![
+            target("gen.h", 0.0, 1.0),
+            target("a.o", 1.0, 4.0),
+            target("b.o", 0.0, 2.0),
+            target("app", 4.0, 5.0),
+        ];
+        let deps = HashMap::from([
+            (
+                "a.o".to_string(),
+                vec!["a.c".to_string(), "gen.h".to_string()],
+            ),
+            ("b.o".to_string(), vec!["b.c".to_string()]),
+            (
+                "app".to_string(),
+                vec!["a.o".to_string(), "b.o".to_string()],
+            ),
+        ]);
+
+        let (time, path) = critical_path(&targets, &deps);
+
+        assert_eq!(time, 5.0);
+        assert_eq!(path, vec!["gen.h", "a.o", "app"]);
+    }
+}
@@ -1,16 +1,49 @@
-use super::cmd::Command;
-use serde::Serialize;
-use std::{sync::mpsc::Receiver, sync::mpsc::Sender, time::Duration};
-use sysinfo::System;
+use super::build::BuildSystem;
+use super::cmd::{output, Command, SpawnOptions};
+use super::error::{Error, Result};
+use super::ninja;
+use super::signal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
 
+pub mod affinity;
+pub mod cgroup;
+pub mod cpu;
 pub mod info;
+pub mod io;
+pub mod memory;
+pub mod procs;
+pub mod psi;
 pub mod rusage;
+pub mod sampler;
+pub mod schedule;
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ProfileSettings {
-    interval: Duration,
-    warmup: Duration,
-    cooldown: Duration,
+    pub interval: Duration,
+    pub warmup: Duration,
+    pub cooldown: Duration,
+    #[serde(default)]
+    pub cgroup: Option<cgroup::CgroupSettings>,
+    #[serde(default)]
+    pub cpus: Option<Vec<usize>>,
+    /// Names of the enabled samplers, all of them when empty.
+    #[serde(default)]
+    pub samplers: Vec<String>,
+    /// Time limit of a single run.
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+    /// Time limit of the whole profile.
+    #[serde(default)]
+    pub total_timeout: Option<Duration>,
+    /// End of `total_timeout`, no run goes past it.
+    #[serde(skip)]
+    pub deadline: Option<Instant>,
 }
 
 impl ProfileSettings {
@@ -19,101 +52,542 @@ impl ProfileSettings {
             interval,
             warmup,
             cooldown,
+            cgroup: None,
+            cpus: None,
+            samplers: Vec::new(),
+            timeout: None,
+            total_timeout: None,
+            deadline: None,
+        }
+    }
+
+    /// Fails once the total time limit ran out.
+    pub fn check_deadline(&self) -> Result<()> {
+        match (self.deadline, self.total_timeout) {
+            (Some(deadline), Some(limit)) if Instant::now() >= deadline => {
+                Err(Error::Timeout(limit))
+            }
+            _ => Ok(()),
         }
     }
 }
 
-#[derive(Serialize)]
+/// Values of all the samplers at the same time, keyed by the sampler name.
+/// Reports written by other versions may have values of unknown samplers.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(from = "DatapointRepr")]
 pub struct ProfileDatapoint {
+    pub elapsed: f64,
+    /// Delay after the scheduled time and time spent sampling, in seconds.
+    #[serde(default)]
+    pub jitter: f64,
+    #[serde(default)]
+    pub overhead: f64,
+    #[serde(flatten)]
+    pub samples: BTreeMap<String, Value>,
+}
+
+impl ProfileDatapoint {
+    /// Returns the values of the sampler `name`, `None` when the sampler was
+    /// not enabled.
+    pub fn get<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        T::deserialize(self.samples.get(name)?).ok()
+    }
+
+    pub fn cpu(&self) -> Option<cpu::CpuSample> {
+        self.get(sampler::CPU)
+    }
+
+    pub fn mem(&self) -> Option<memory::MemorySample> {
+        self.get(sampler::MEM)
+    }
+
+    pub fn psi(&self) -> Option<psi::PsiSample> {
+        self.get(sampler::PSI)
+    }
+
+    pub fn procs(&self) -> Option<procs::ProcessTreeSample> {
+        self.get(sampler::PROCS)
+    }
+}
+
+#[derive(Deserialize)]
+struct DatapointRepr {
     elapsed: f64,
-    cpu_usage: f32,
-    cpus_utilization: Vec<f32>,
-    memory_usage: u64,
+    #[serde(default)]
+    jitter: f64,
+    #[serde(default)]
+    overhead: f64,
+    #[serde(flatten)]
+    samples: BTreeMap<String, Value>,
+}
+
+impl From<DatapointRepr> for ProfileDatapoint {
+    fn from(repr: DatapointRepr) -> ProfileDatapoint {
+        let mut samples = repr.samples;
+        samples.retain(|_, value| !value.is_null());
+        migrate_flat_fields(&mut samples);
+        ProfileDatapoint {
+            elapsed: repr.elapsed,
+            jitter: repr.jitter,
+            overhead: repr.overhead,
+            samples,
+        }
+    }
 }
 
-#[derive(Serialize)]
+/// Moves the flat fields of the reports written before the samplers were
+/// introduced into the values of the corresponding samplers.
+fn migrate_flat_fields(samples: &mut BTreeMap<String, Value>) {
+    const FIELDS: [(&str, &str, &str); 5] = [
+        ("cpu_usage", sampler::CPU, "usage"),
+        ("cpus_utilization", sampler::CPU, "cores"),
+        ("memory_usage", sampler::MEM, "used"),
+        ("build_cpu_usage", sampler::PROCS, "cpu_usage"),
+        ("build_memory_usage", sampler::PROCS, "memory_usage"),
+    ];
+    const BUILD_IO: [(&str, &str); 4] = [
+        ("build_read_bytes", "read_bytes"),
+        ("build_write_bytes", "write_bytes"),
+        ("build_read_rate", "read_rate"),
+        ("build_write_rate", "write_rate"),
+    ];
+
+    let mut moved: Vec<(&str, String, Value)> = Vec::new();
+    for (field, name, key) in FIELDS {
+        if let Some(value) = samples.remove(field) {
+            moved.push((name, key.to_string(), value));
+        }
+    }
+    if let Some(Value::Object(memory)) = samples.remove("memory") {
+        for (key, value) in memory {
+            match key.as_str() {
+                "build_pss" => moved.push((sampler::PROCS, "pss_usage".to_string(), value)),
+                "build_rss" => {}
+                _ => moved.push((sampler::MEM, key, value)),
+            }
+        }
+    }
+    if let Some(Value::Object(io)) = samples.get_mut(sampler::IO) {
+        for (field, key) in BUILD_IO {
+            if let Some(value) = io.remove(field) {
+                moved.push((sampler::PROCS, key.to_string(), value));
+            }
+        }
+    }
+
+    for (name, key, value) in moved {
+        if let Value::Object(values) = samples
+            .entry(name.to_string())
+            .or_insert_with(|| Value::Object(Default::default()))
+        {
+            values.entry(key).or_insert(value);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RunStatus {
+    #[default]
+    Success,
+    Failed {
+        code: i32,
+    },
+    Signaled {
+        signal: i32,
+    },
+    /// bsprof received `signal` and stopped the build.
+    Interrupted {
+        signal: i32,
+    },
+    /// The build ran out of time and was terminated.
+    Timeout,
+}
+
+impl RunStatus {
+    pub fn from_exit_status(status: std::process::ExitStatus) -> RunStatus {
+        match (status.code(), status.signal()) {
+            (Some(0), _) => RunStatus::Success,
+            (Some(code), _) => RunStatus::Failed { code },
+            (None, Some(signal)) => RunStatus::Signaled { signal },
+            (None, None) => RunStatus::Failed { code: -1 },
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self == RunStatus::Success
+    }
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RunStatus::Success => write!(f, "success"),
+            RunStatus::Failed { code } => write!(f, "exit code {}", code),
+            RunStatus::Signaled { signal } => write!(f, "killed by signal {}", signal),
+            RunStatus::Interrupted { signal } => write!(f, "interrupted by signal {}", signal),
+            RunStatus::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct ProfileResult {
     pub elapsed_time: f64,
     pub jobs: u32,
+    #[serde(default)]
+    pub run: u32,
+    #[serde(default)]
+    pub status: RunStatus,
     pub cmd_name: String,
     pub cmd_args: Vec<String>,
     pub rusage: rusage::Rusage,
+    #[serde(default)]
+    pub clean_rusage: Option<rusage::Rusage>,
     pub datapoints: Vec<ProfileDatapoint>,
+    #[serde(default)]
+    pub processes: Vec<procs::ProcessLifetime>,
+    pub ninja: Option<ninja::NinjaRun>,
+    #[serde(default)]
+    pub log: Option<output::RunLog>,
+    #[serde(default)]
+    pub cgroup: Option<cgroup::CgroupSample>,
+    #[serde(default)]
+    pub io_check: Option<io::IoCrossCheck>,
+    #[serde(default)]
+    pub sampling: Option<schedule::SamplingStats>,
 }
 
+/// Receives every data point as soon as it is sampled.
+pub type DatapointSink = Box<dyn FnMut(&ProfileDatapoint) + Send>;
+
 #[derive(PartialEq)]
 enum ThreadCommand {
+    Track(u32),
     Stop,
 }
 
-fn get_data_point(sys: &mut sysinfo::System, start_time: &std::time::Instant) -> ProfileDatapoint {
-    let cpus_data = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect();
-    let elapsed_time = std::time::Instant::now() - start_time.clone();
+/// Samplers run by the monitor thread on every tick.
+struct Monitor {
+    samplers: Vec<Box<dyn sampler::Sampler>>,
+    start_time: Instant,
+}
 
-    sys.refresh_memory();
-    sys.refresh_cpu();
+impl Monitor {
+    fn new(names: &[String], cgroup: Option<PathBuf>) -> Monitor {
+        let mut samplers = sampler::create(names, cgroup.is_some());
+        // counting the cores in /proc/stat is much cheaper than a sysinfo refresh
+        let num_cpus = match cpu::StatReader::new().read().1.len() {
+            0 => info::get_cpu_count(),
+            cores => cores as u32,
+        };
+        let context = sampler::SamplerContext { num_cpus, cgroup };
+        for sampler in samplers.iter_mut() {
+            sampler.init(&context);
+        }
 
-    ProfileDatapoint {
-        elapsed: elapsed_time.as_secs_f64(),
-        cpu_usage: sys.global_cpu_info().cpu_usage(),
-        memory_usage: sys.used_memory(),
-        cpus_utilization: cpus_data,
+        Monitor {
+            samplers,
+            start_time: Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.start_time.elapsed().as_secs_f64()
+    }
+
+    fn get_data_point(&mut self, deadline: Instant) -> ProfileDatapoint {
+        let sample_start = Instant::now();
+        let elapsed = self.elapsed();
+        let mut samples = BTreeMap::new();
+        for i in 0..self.samplers.len() {
+            // some samplers take a while, rates use the time they were read
+            let now = self.elapsed();
+            let sampler = &mut self.samplers[i];
+            samples.insert(sampler.name().to_string(), sampler.sample(now));
+        }
+        ProfileDatapoint {
+            elapsed,
+            jitter: sample_start
+                .saturating_duration_since(deadline)
+                .as_secs_f64(),
+            overhead: sample_start.elapsed().as_secs_f64(),
+            samples,
+        }
+    }
+
+    fn finalize(&mut self) -> BTreeMap<String, Value> {
+        self.samplers
+            .iter_mut()
+            .filter_map(|sampler| Some((sampler.name().to_string(), sampler.finalize()?)))
+            .collect()
     }
 }
 
-fn monitor_thread(rx: Receiver<ThreadCommand>, interval: Duration) -> Vec<ProfileDatapoint> {
-    let mut sys = System::new_all();
+struct MonitorData {
+    datapoints: Vec<ProfileDatapoint>,
+    finals: BTreeMap<String, Value>,
+    sampling: Option<schedule::SamplingStats>,
+}
+
+fn monitor_thread(
+    rx: Receiver<ThreadCommand>,
+    interval: Duration,
+    samplers: Vec<String>,
+    cgroup: Option<PathBuf>,
+    mut sink: Option<DatapointSink>,
+) -> MonitorData {
+    let mut monitor = Monitor::new(&samplers, cgroup);
+    let mut schedule = schedule::Schedule::new(monitor.start_time, interval);
     let mut datapoints = Vec::<ProfileDatapoint>::new();
-    let start_time = std::time::Instant::now();
 
-    loop {
-        datapoints.push(get_data_point(&mut sys, &start_time));
+    let mut push = |datapoint: ProfileDatapoint| {
+        if let Some(sink) = sink.as_mut() {
+            sink(&datapoint);
+        }
+        datapoints.push(datapoint);
+    };
+
+    'sampling: loop {
+        push(monitor.get_data_point(schedule.deadline()));
 
-        match rx.recv_timeout(interval) {
-            Ok(cmd) => {
-                if cmd == ThreadCommand::Stop {
-                    break;
+        // commands do not move the deadline of the next sample
+        let deadline = schedule.advance(Instant::now());
+        loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(ThreadCommand::Track(pid)) => {
+                    let elapsed = monitor.elapsed();
+                    for sampler in monitor.samplers.iter_mut() {
+                        sampler.track(pid, elapsed);
+                    }
+                }
+                Ok(ThreadCommand::Stop) => {
+                    // the state at the end of the run, off the schedule
+                    push(monitor.get_data_point(Instant::now()));
+                    break 'sampling;
                 }
+                Err(RecvTimeoutError::Disconnected) => break 'sampling,
+                Err(RecvTimeoutError::Timeout) => break,
             }
-            Err(_) => {}
         }
     }
 
-    datapoints
+    let jitter: Vec<f64> = datapoints.iter().map(|d| d.jitter).collect();
+    let overhead: Vec<f64> = datapoints.iter().map(|d| d.overhead).collect();
+    MonitorData {
+        sampling: schedule::SamplingStats::new(interval, schedule.missed(), &jitter, &overhead),
+        finals: monitor.finalize(),
+        datapoints,
+    }
 }
 
-pub fn profile(build_cmd: &Command, settings: &ProfileSettings, jobs: u32) -> ProfileResult {
+pub fn profile(
+    build_cmd: &Command,
+    build_system: &BuildSystem,
+    settings: &ProfileSettings,
+    jobs: u32,
+    log: Option<(&output::OutputCapture, String)>,
+    sink: Option<DatapointSink>,
+) -> Result<ProfileResult> {
+    let cgroup = match &settings.cgroup {
+        Some(cgroup_settings) => Some(cgroup::Cgroup::create(
+            cgroup_settings,
+            &format!("bsprof-{}-{}", std::process::id(), jobs),
+        )?),
+        None => None,
+    };
+
     // run monitroing thread and spawn command
     let (tx, rx): (Sender<ThreadCommand>, Receiver<ThreadCommand>) = std::sync::mpsc::channel();
-    let check_interval = settings.interval.clone();
-    let monitor = std::thread::spawn(move || monitor_thread(rx, check_interval));
+    let check_interval = settings.interval;
+    let cgroup_path = cgroup.as_ref().map(|c| c.path().to_path_buf());
+    let samplers = settings.samplers.clone();
+    let monitor =
+        std::thread::spawn(move || monitor_thread(rx, check_interval, samplers, cgroup_path, sink));
     let mut cmd = build_cmd.clone();
 
     // warmup
     std::thread::sleep(settings.warmup);
 
     // add jobs
-    cmd.add_jobs(jobs);
+    cmd.add_args(&build_system.jobs_args(jobs));
+    let ninja_build = ninja::NinjaBuild::detect(&cmd);
 
-    let start_time = std::time::Instant::now();
-    cmd.run().unwrap();
-    let elapsed_time = std::time::Instant::now() - start_time;
+    let start_time = Instant::now();
+    let deadline = settings
+        .timeout
+        .map(|timeout| start_time + timeout)
+        .into_iter()
+        .chain(settings.deadline)
+        .min();
+    let child = match signal::received() {
+        Some(signal) => Err(Error::Interrupted(signal)),
+        None => settings.check_deadline().and_then(|()| {
+            cmd.spawn_with(&SpawnOptions {
+                piped: log.is_some(),
+                cgroup_procs: cgroup.as_ref().map(|c| c.procs_fd()),
+                cpus: settings.cpus.clone(),
+                process_group: true,
+            })
+        }),
+    };
+    let mut logger = None;
+    let mut stopped = None;
+    let status = match child {
+        Ok(mut child) => {
+            tx.send(ThreadCommand::Track(child.id())).unwrap();
+            let watch = signal::GroupWatch::start(child.id(), deadline);
+            let status = match log.map(|(capture, path)| capture.tee(&mut child, &path, start_time))
+            {
+                Some(Ok(handle)) => {
+                    logger = Some(handle);
+                    cmd.wait_rusage(&mut child)
+                }
+                Some(Err(e)) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    Err(e)
+                }
+                None => cmd.wait_rusage(&mut child),
+            };
+            stopped = watch.stop(child.id());
+            status
+        }
+        Err(e) => Err(e),
+    };
+    // a timed out run took its time limit, not the grace period after it
+    let end = match (stopped, deadline) {
+        (Some(signal::Stop::Timeout), Some(deadline)) => deadline,
+        _ => Instant::now(),
+    };
+    let elapsed_time = end - start_time;
+    let run_log = logger.map(|handle| handle.join().unwrap());
+
+    let (status, usage) = match status {
+        Ok((status, usage)) => match stopped {
+            Some(signal::Stop::Signal(signal)) => (RunStatus::Interrupted { signal }, usage),
+            Some(signal::Stop::Timeout) => (RunStatus::Timeout, usage),
+            None => (RunStatus::from_exit_status(status), usage),
+        },
+        Err(e) => {
+            tx.send(ThreadCommand::Stop).unwrap();
+            monitor.join().unwrap();
+            if let Some(cgroup) = cgroup {
+                cgroup.remove();
+            }
+            return Err(e);
+        }
+    };
 
     // cooldown
-    std::thread::sleep(settings.cooldown);
+    if stopped.is_none() {
+        std::thread::sleep(settings.cooldown);
+    }
 
     // stop monitoring thread
     tx.send(ThreadCommand::Stop).unwrap();
-    let datapoints = monitor.join().unwrap();
-    let usage = rusage::get_process_rusage();
+    let MonitorData {
+        datapoints,
+        finals,
+        sampling,
+    } = monitor.join().unwrap();
+    let processes = finals
+        .get(sampler::PROCS)
+        .and_then(|value| Vec::<procs::ProcessLifetime>::deserialize(value).ok())
+        .unwrap_or_default();
+    let cgroup_usage = cgroup.map(|cgroup| {
+        let usage = cgroup::sample(cgroup.path(), None, 0.0, 1);
+        cgroup.remove();
+        usage
+    });
+    let io_check =
+        io::IoCrossCheck::new(&usage, datapoints.last().and_then(|d| d.procs()).as_ref());
+    let ninja_run = ninja_build.and_then(|build| build.analyze(elapsed_time.as_secs_f64()));
 
     // return report
-    ProfileResult {
+    Ok(ProfileResult {
         elapsed_time: elapsed_time.as_secs_f64(),
-        jobs: jobs,
+        jobs,
+        run: 0,
+        status,
         cmd_name: cmd.name.clone(),
         cmd_args: cmd.args.to_vec(),
         rusage: usage,
-        datapoints: datapoints,
+        clean_rusage: None,
+        datapoints,
+        processes,
+        ninja: ninja_run,
+        log: run_log,
+        cgroup: cgroup_usage,
+        io_check: Some(io_check),
+        sampling,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_datapoint() {
+        let datapoint: ProfileDatapoint = serde_json::from_str(
+            r#"{"elapsed": 1.5, "cpu_usage": 50.0, "cpus_utilization": [40.0, 60.0],
+                "memory_usage": 1000, "build_cpu_usage": 25.0, "build_memory_usage": 200,
+                "cgroup": null, "memory": {"available": 3000, "build_pss": 150},
+                "io": {"iowait": 2.0, "disks": [], "build_read_bytes": 4096}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            datapoint.cpu(),
+            Some(cpu::CpuSample {
+                usage: 50.0,
+                cores: vec![40.0, 60.0],
+            })
+        );
+        let mem = datapoint.mem().unwrap();
+        assert_eq!((mem.used, mem.available), (1000, 3000));
+        let build = datapoint.procs().unwrap();
+        assert_eq!(
+            (
+                build.cpu_usage,
+                build.memory_usage,
+                build.pss_usage,
+                build.read_bytes
+            ),
+            (25.0, 200, 150, 4096)
+        );
+        assert!(!datapoint.samples.contains_key(sampler::CGROUP));
+        assert!(!datapoint.samples["io"]
+            .as_object()
+            .unwrap()
+            .contains_key("build_read_bytes"));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let args = vec!["-c".to_string(), "sleep 30 & sleep 30".to_string()];
+        let cmd = Command::new(&"sh".to_string(), &args);
+        let mut settings =
+            ProfileSettings::new(Duration::from_millis(50), Duration::ZERO, Duration::ZERO);
+        settings.samplers = vec![sampler::CPU.to_string()];
+        settings.timeout = Some(Duration::from_millis(300));
+
+        let result = profile(
+            &cmd,
+            &BuildSystem::Generic(String::new()),
+            &settings,
+            1,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(result.status, RunStatus::Timeout);
+        assert!((result.elapsed_time - 0.3).abs() < 1e-6);
+        assert!(!result.datapoints.is_empty());
+        assert!(result.datapoints.iter().all(|d| d.cpu().is_some()));
     }
 }
@@ -0,0 +1,320 @@
+//! Transient cgroup v2 running the build, giving exact build-only CPU, memory
+//! and I/O accounting and optional resource limits.
+
+use super::sampler::{Sampler, SamplerContext, CGROUP};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+const CPU_PERIOD_USEC: u64 = 100000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CgroupSettings {
+    /// Parent cgroup directory, the cgroup of bsprof itself when not given.
+    pub parent: Option<String>,
+    /// CPU bandwidth limit in CPUs, written to `cpu.max`.
+    pub cpu_max: Option<f64>,
+    /// Memory limit in bytes, written to `memory.max`.
+    pub memory_max: Option<u64>,
+}
+
+/// Counters read from the cgroup interface files, `None` when the file or
+/// the controller is not available.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CgroupSample {
+    pub cpu_usage: f32,
+    pub usage_usec: Option<u64>,
+    pub user_usec: Option<u64>,
+    pub system_usec: Option<u64>,
+    pub memory_current: Option<u64>,
+    pub memory_peak: Option<u64>,
+    pub io_read_bytes: Option<u64>,
+    pub io_write_bytes: Option<u64>,
+    pub pids_current: Option<u64>,
+}
+
+pub struct Cgroup {
+    path: PathBuf,
+    procs: std::fs::File,
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn write_file(path: &Path, value: &str) -> Result<()> {
+    std::fs::write(path, value).map_err(|e| Error::io(&path_str(path), e))
+}
+
+/// Finds the cgroup of the current process in the cgroup v2 hierarchy.
+fn current_cgroup() -> Option<PathBuf> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let mount_point = mountinfo.lines().find_map(|line| {
+        let (fields, fs) = line.split_once(" - ")?;
+        if fs.split_whitespace().next()? == "cgroup2" {
+            fields.split_whitespace().nth(4)
+        } else {
+            None
+        }
+    })?;
+
+    let cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroup.lines().find_map(|line| line.strip_prefix("0::"))?;
+    Some(Path::new(mount_point).join(path.trim_start_matches('/')))
+}
+
+fn apply_limits(path: &Path, settings: &CgroupSettings) -> Result<()> {
+    if let Some(cpus) = settings.cpu_max {
+        let quota = (cpus * CPU_PERIOD_USEC as f64).round() as u64;
+        write_file(
+            &path.join("cpu.max"),
+            &format!("{} {}", quota, CPU_PERIOD_USEC),
+        )?;
+    }
+    if let Some(memory) = settings.memory_max {
+        write_file(&path.join("memory.max"), &memory.to_string())?;
+    }
+    Ok(())
+}
+
+/// Controllers needed by the limits which are not enabled in the
+/// `cgroup.subtree_control` of the parent.
+fn missing_controllers(settings: &CgroupSettings, subtree_control: &str) -> Vec<&'static str> {
+    let mut needed = Vec::new();
+    if settings.cpu_max.is_some() {
+        needed.push("cpu");
+    }
+    if settings.memory_max.is_some() {
+        needed.push("memory");
+    }
+    needed
+        .into_iter()
+        .filter(|controller| !subtree_control.split_whitespace().any(|c| c == *controller))
+        .collect()
+}
+
+impl Cgroup {
+    /// Creates the cgroup `name` under the configured parent, enabling the
+    /// controllers delegated to it and applying the limits.
+    pub fn create(settings: &CgroupSettings, name: &str) -> Result<Cgroup> {
+        let parent = match &settings.parent {
+            Some(parent) => PathBuf::from(parent),
+            None => current_cgroup()
+                .ok_or_else(|| Error::invalid_argument("cgroup v2 hierarchy not found"))?,
+        };
+
+        let controllers = std::fs::read_to_string(parent.join("cgroup.controllers"))
+            .map_err(|e| Error::io(&path_str(&parent), e))?;
+        // not fatal, the parent may still hold processes or not delegate
+        // some of the controllers
+        for controller in controllers.split_whitespace() {
+            if ["cpu", "memory", "io", "pids"].contains(&controller) {
+                let _ = std::fs::write(
+                    parent.join("cgroup.subtree_control"),
+                    format!("+{}", controller),
+                );
+            }
+        }
+
+        // a cgroup holding processes, like the one of bsprof, cannot enable
+        // controllers for its children
+        let subtree_control = parent.join("cgroup.subtree_control");
+        let enabled = std::fs::read_to_string(&subtree_control)
+            .map_err(|e| Error::io(&path_str(&subtree_control), e))?;
+        let missing = missing_controllers(settings, &enabled);
+        if !missing.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "the {} controller is not enabled in {}, pass a delegated cgroup without processes with --cgroup=PARENT",
+                missing.join(" and "),
+                path_str(&subtree_control)
+            )));
+        }
+
+        let path = parent.join(name);
+        if path.exists() {
+            let _ = std::fs::remove_dir(&path);
+        }
+        std::fs::create_dir(&path).map_err(|e| Error::io(&path_str(&path), e))?;
+
+        let limits = apply_limits(&path, settings);
+        let procs_path = path.join("cgroup.procs");
+        let procs = limits.and_then(|_| {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&procs_path)
+                .map_err(|e| Error::io(&path_str(&procs_path), e))
+        });
+        let procs = match procs {
+            Ok(procs) => procs,
+            Err(e) => {
+                let _ = std::fs::remove_dir(&path);
+                return Err(e);
+            }
+        };
+
+        Ok(Cgroup { path, procs })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Descriptor of `cgroup.procs`, the child joins the cgroup by writing
+    /// to it before exec.
+    pub fn procs_fd(&self) -> RawFd {
+        self.procs.as_raw_fd()
+    }
+
+    pub fn remove(self) {
+        if let Err(e) = std::fs::remove_dir(&self.path) {
+            println!(
+                "Warning: failed to remove cgroup {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn read_keyed(path: &Path, keys: &[&str]) -> Vec<Option<u64>> {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    keys.iter()
+        .map(|key| {
+            content.lines().find_map(|line| {
+                let (name, value) = line.split_once(' ')?;
+                if name == *key {
+                    value.trim().parse().ok()
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+fn read_value(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Sums the read and written bytes over all devices listed in `io.stat`.
+fn parse_io_stat(content: &str) -> (u64, u64) {
+    let mut read = 0;
+    let mut written = 0;
+    for field in content.split_whitespace() {
+        if let Some(bytes) = field.strip_prefix("rbytes=") {
+            read += bytes.parse::<u64>().unwrap_or(0);
+        } else if let Some(bytes) = field.strip_prefix("wbytes=") {
+            written += bytes.parse::<u64>().unwrap_or(0);
+        }
+    }
+    (read, written)
+}
+
+/// Reads the counters of the cgroup at `path`, computing the CPU usage in
+/// percent of all CPUs from the previous sample.
+pub fn sample(
+    path: &Path,
+    last: Option<&CgroupSample>,
+    interval: f64,
+    num_cpus: u32,
+) -> CgroupSample {
+    let cpu = read_keyed(
+        &path.join("cpu.stat"),
+        &["usage_usec", "user_usec", "system_usec"],
+    );
+    let io = std::fs::read_to_string(path.join("io.stat"))
+        .ok()
+        .map(|content| parse_io_stat(&content));
+
+    let mut sample = CgroupSample {
+        cpu_usage: 0.0,
+        usage_usec: cpu[0],
+        user_usec: cpu[1],
+        system_usec: cpu[2],
+        memory_current: read_value(&path.join("memory.current")),
+        memory_peak: read_value(&path.join("memory.peak")),
+        io_read_bytes: io.map(|(read, _)| read),
+        io_write_bytes: io.map(|(_, written)| written),
+        pids_current: read_value(&path.join("pids.current")),
+    };
+
+    if let (Some(usage), Some(last_usage)) = (sample.usage_usec, last.and_then(|l| l.usage_usec)) {
+        if interval > 0.0 {
+            sample.cpu_usage = (usage.saturating_sub(last_usage) as f64 / 1e6 / interval * 100.0
+                / num_cpus.max(1) as f64) as f32;
+        }
+    }
+    sample
+}
+
+/// Samples the cgroup of the build, the CPU usage is computed from the
+/// previous sample.
+pub struct CgroupSampler {
+    path: Option<PathBuf>,
+    num_cpus: u32,
+    last: Option<(f64, CgroupSample)>,
+}
+
+impl CgroupSampler {
+    pub fn new() -> CgroupSampler {
+        CgroupSampler {
+            path: None,
+            num_cpus: 1,
+            last: None,
+        }
+    }
+}
+
+impl Sampler for CgroupSampler {
+    fn name(&self) -> &'static str {
+        CGROUP
+    }
+
+    fn init(&mut self, context: &SamplerContext) {
+        self.path = context.cgroup.clone();
+        self.num_cpus = context.num_cpus;
+    }
+
+    fn sample(&mut self, elapsed: f64) -> serde_json::Value {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return serde_json::Value::Null,
+        };
+        let last = self.last.as_ref();
+        let interval = elapsed - last.map_or(0.0, |(time, _)| *time);
+        let sample = sample(path, last.map(|(_, l)| l), interval, self.num_cpus);
+        let value = serde_json::to_value(&sample).unwrap_or_default();
+        self.last = Some((elapsed, sample));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_io_stat() {
+        let content = "8:0 rbytes=4096 wbytes=8192 rios=1 wios=2 dbytes=0 dios=0\n\
+                       259:0 rbytes=1024 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n";
+        assert_eq!(parse_io_stat(content), (5120, 8192));
+        assert_eq!(parse_io_stat(""), (0, 0));
+    }
+
+    #[test]
+    fn test_missing_controllers() {
+        let settings = CgroupSettings {
+            parent: None,
+            cpu_max: Some(2.0),
+            memory_max: Some(1 << 30),
+        };
+        assert_eq!(
+            missing_controllers(&settings, "cpu io pids\n"),
+            vec!["memory"]
+        );
+        assert!(missing_controllers(&settings, "cpu memory\n").is_empty());
+        assert!(missing_controllers(&CgroupSettings::default(), "").is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+//! System wide and per-core CPU usage read directly from /proc/stat, cheap
+//! enough for sampling intervals of a few milliseconds.
+
+use super::sampler::{Sampler, SamplerContext, CPU};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CpuSample {
+    /// Usage of all CPUs in percent.
+    pub usage: f32,
+    pub cores: Vec<f32>,
+}
+
+/// Jiffies of a `cpu` line of /proc/stat.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub iowait: u64,
+    pub total: u64,
+}
+
+impl CpuTimes {
+    /// Busy time in percent since `last`, `None` when no clock tick (10 ms
+    /// with the usual USER_HZ of 100) elapsed in between.
+    fn usage(&self, last: &CpuTimes) -> Option<f32> {
+        let total = self.total.saturating_sub(last.total);
+        if total == 0 {
+            return None;
+        }
+        Some((self.busy.saturating_sub(last.busy) as f64 / total as f64 * 100.0) as f32)
+    }
+}
+
+fn parse_cpu_line(line: &str) -> Option<CpuTimes> {
+    let times: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|value| value.parse().ok())
+        .collect();
+    // guest times are already included in user and nice
+    let total = times.iter().take(8).sum();
+    let idle = *times.get(3)?;
+    let iowait = *times.get(4)?;
+    Some(CpuTimes {
+        busy: total - idle - iowait,
+        iowait,
+        total,
+    })
+}
+
+/// Returns the times of the aggregate `cpu` line and of every online core.
+pub fn parse_stat(content: &str) -> (Option<CpuTimes>, Vec<CpuTimes>) {
+    let mut all = None;
+    let mut cores = Vec::new();
+    for line in content.lines().take_while(|line| line.starts_with("cpu")) {
+        if line.starts_with("cpu ") {
+            all = parse_cpu_line(line);
+        } else if let Some(times) = parse_cpu_line(line) {
+            cores.push(times);
+        }
+    }
+    (all, cores)
+}
+
+/// Keeps /proc/stat open and rereads it into the same buffer.
+pub struct StatReader {
+    file: Option<std::fs::File>,
+    buffer: String,
+}
+
+impl StatReader {
+    pub fn new() -> StatReader {
+        StatReader {
+            file: std::fs::File::open("/proc/stat").ok(),
+            buffer: String::new(),
+        }
+    }
+
+    pub fn read(&mut self) -> (Option<CpuTimes>, Vec<CpuTimes>) {
+        self.buffer.clear();
+        let read = match &mut self.file {
+            Some(file) => file
+                .rewind()
+                .and_then(|_| file.read_to_string(&mut self.buffer))
+                .is_ok(),
+            None => false,
+        };
+        if !read {
+            return (None, Vec::new());
+        }
+        parse_stat(&self.buffer)
+    }
+}
+
+/// Usage over the ticks elapsed since the last change. Intervals shorter
+/// than a tick often see none, they repeat the previous usage instead of a
+/// false 0%.
+pub struct CpuSampler {
+    reader: StatReader,
+    last: (Option<CpuTimes>, Vec<CpuTimes>),
+    usage: CpuSample,
+}
+
+impl CpuSampler {
+    pub fn new() -> CpuSampler {
+        CpuSampler {
+            reader: StatReader::new(),
+            last: (None, Vec::new()),
+            usage: CpuSample::default(),
+        }
+    }
+}
+
+/// Updates `usage` and `last` when a tick elapsed since `last`.
+fn update(usage: &mut f32, last: &mut CpuTimes, times: CpuTimes) {
+    if let Some(value) = times.usage(last) {
+        *usage = value;
+        *last = times;
+    }
+}
+
+impl Sampler for CpuSampler {
+    fn name(&self) -> &'static str {
+        CPU
+    }
+
+    fn init(&mut self, _context: &SamplerContext) {
+        self.last = self.reader.read();
+    }
+
+    fn sample(&mut self, _elapsed: f64) -> serde_json::Value {
+        let (all, cores) = self.reader.read();
+        match (all, self.last.0.as_mut()) {
+            (Some(all), Some(last)) => update(&mut self.usage.usage, last, all),
+            (all, _) => self.last.0 = all,
+        }
+        self.usage.cores.resize(cores.len(), 0.0);
+        for (i, core) in cores.into_iter().enumerate() {
+            match self.last.1.get_mut(i) {
+                Some(last) => update(&mut self.usage.cores[i], last, core),
+                None => self.last.1.push(core),
+            }
+        }
+        serde_json::to_value(&self.usage).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let content = "cpu  100 0 50 800 40 0 10 0 0 0\n\
+                       cpu0 60 0 30 400 10 0 0 0 0 0\n\
+                       cpu1 40 0 20 400 30 0 10 0 0 0\n\
+                       intr 12345 0 0\n";
+        let (all, cores) = parse_stat(content);
+
+        assert_eq!(
+            all,
+            Some(CpuTimes {
+                busy: 160,
+                iowait: 40,
+                total: 1000,
+            })
+        );
+        assert_eq!(cores.len(), 2);
+        let last = CpuTimes {
+            busy: 40,
+            iowait: 10,
+            total: 400,
+        };
+        assert_eq!(cores[0].usage(&last), Some(50.0));
+        assert_eq!(cores[0].usage(&cores[0]), None);
+    }
+
+    #[test]
+    fn test_no_tick() {
+        let times = |busy, total| CpuTimes {
+            busy,
+            iowait: 0,
+            total,
+        };
+        let mut usage = 0.0;
+        let mut last = times(0, 0);
+
+        update(&mut usage, &mut last, times(8, 10));
+        assert_eq!(usage, 80.0);
+        // no tick since the last sample, the usage is kept
+        update(&mut usage, &mut last, times(8, 10));
+        assert_eq!((usage, last), (80.0, times(8, 10)));
+        update(&mut usage, &mut last, times(9, 12));
+        assert_eq!(usage, 50.0);
+    }
+}
@@ -1,12 +1,20 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sysinfo::System;
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct SystemInfo {
     pub num_cpus: u32,
     pub cpu_name: String,
     pub total_memory: u64,
     pub os: String,
+    /// CPUs the build is pinned to, `num_cpus` and `total_memory` are then
+    /// the values visible to the build and the host ones are kept below.
+    #[serde(default)]
+    pub cpu_list: Option<String>,
+    #[serde(default)]
+    pub host_num_cpus: Option<u32>,
+    #[serde(default)]
+    pub host_total_memory: Option<u64>,
 }
 
 pub fn get_cpu_count() -> u32 {
@@ -24,6 +32,9 @@ pub fn get_system_info() -> SystemInfo {
         cpu_name: cpu.vendor_id().to_string() + " / " + cpu.brand(),
         total_memory: sys.total_memory(),
         os: os_name,
+        cpu_list: None,
+        host_num_cpus: None,
+        host_total_memory: None,
     }
 }
 
@@ -33,20 +44,14 @@ fn get_system_name() -> String {
     match System::name() {
         Some(name) => {
             os_name = name;
-            match System::os_version() {
-                Some(version) => {
-                    os_name += " ";
-                    os_name += &version;
-                }
-                None => {}
+            if let Some(version) = System::os_version() {
+                os_name += " ";
+                os_name += &version;
             }
-            match System::kernel_version() {
-                Some(version) => {
-                    os_name += " (";
-                    os_name += &version;
-                    os_name += ")";
-                }
-                None => {}
+            if let Some(version) = System::kernel_version() {
+                os_name += " (";
+                os_name += &version;
+                os_name += ")";
             }
         }
         None => {
@@ -57,10 +62,38 @@ fn get_system_name() -> String {
 }
 
 impl SystemInfo {
+    /// System as seen by a build pinned to `cpus` and limited to `memory`.
+    pub fn restrict(&self, cpus: Option<&[usize]>, memory: Option<u64>) -> SystemInfo {
+        let mut info = self.clone();
+        if let Some(cpus) = cpus {
+            info.num_cpus = cpus.len() as u32;
+            info.cpu_list = Some(super::affinity::format_cpu_list(cpus));
+            info.host_num_cpus = Some(self.num_cpus);
+        }
+        if let Some(memory) = memory.filter(|m| *m < self.total_memory) {
+            info.total_memory = memory;
+            info.host_total_memory = Some(self.total_memory);
+        }
+        info
+    }
+
     pub fn print(&self) {
         println!("OS name       \t: {}", self.os);
         println!("CPU name      \t: {}", self.cpu_name);
-        println!("Number of CPUs\t: {}", self.num_cpus);
-        println!("Total memory  \t: {} MB", self.total_memory / 1024 / 1024);
+        match (&self.cpu_list, self.host_num_cpus) {
+            (Some(list), Some(host)) => println!(
+                "Number of CPUs\t: {} ({} of {} host CPUs)",
+                self.num_cpus, list, host
+            ),
+            _ => println!("Number of CPUs\t: {}", self.num_cpus),
+        }
+        match self.host_total_memory {
+            Some(host) => println!(
+                "Total memory  \t: {} MB (limited, host {} MB)",
+                self.total_memory / 1024 / 1024,
+                host / 1024 / 1024
+            ),
+            None => println!("Total memory  \t: {} MB", self.total_memory / 1024 / 1024),
+        }
     }
 }
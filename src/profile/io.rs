@@ -0,0 +1,204 @@
+//! Disk I/O sampling from /proc/diskstats and /proc/stat.
+
+use super::cpu::{CpuTimes, StatReader};
+use super::procs::ProcessTreeSample;
+use super::sampler::{Sampler, IO};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DiskSample {
+    pub device: String,
+    pub read_bytes_rate: f64,
+    pub write_bytes_rate: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct IoSample {
+    /// Time the CPUs spent waiting for I/O, in percent.
+    pub iowait: f32,
+    pub disks: Vec<DiskSample>,
+}
+
+/// Comparison of the bytes sampled from /proc/<pid>/io with the rusage block
+/// counters, processes living shorter than the sampling interval are only
+/// accounted for by the latter.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IoCrossCheck {
+    pub rusage_read_bytes: u64,
+    pub rusage_write_bytes: u64,
+    pub sampled_read_bytes: u64,
+    pub sampled_write_bytes: u64,
+}
+
+impl IoCrossCheck {
+    pub fn new(
+        rusage: &super::rusage::Rusage,
+        sampled: Option<&ProcessTreeSample>,
+    ) -> IoCrossCheck {
+        IoCrossCheck {
+            rusage_read_bytes: rusage.inblock * SECTOR_SIZE,
+            rusage_write_bytes: rusage.oublock * SECTOR_SIZE,
+            sampled_read_bytes: sampled.map_or(0, |s| s.read_bytes),
+            sampled_write_bytes: sampled.map_or(0, |s| s.write_bytes),
+        }
+    }
+
+    /// Fraction of the rusage totals covered by the samples, 1 when there
+    /// was no I/O.
+    pub fn coverage(&self) -> f64 {
+        let total = self.rusage_read_bytes + self.rusage_write_bytes;
+        if total == 0 {
+            return 1.0;
+        }
+        (self.sampled_read_bytes + self.sampled_write_bytes) as f64 / total as f64
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct DiskCounters {
+    reads: u64,
+    read_sectors: u64,
+    writes: u64,
+    write_sectors: u64,
+}
+
+fn parse_diskstats(content: &str) -> HashMap<String, DiskCounters> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let number = |i: usize| fields.get(i)?.parse::<u64>().ok();
+            Some((
+                fields.get(2)?.to_string(),
+                DiskCounters {
+                    reads: number(3)?,
+                    read_sectors: number(5)?,
+                    writes: number(7)?,
+                    write_sectors: number(9)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Partitions, loop and RAM devices are skipped.
+fn is_disk(device: &str) -> bool {
+    !device.starts_with("loop")
+        && !device.starts_with("ram")
+        && std::path::Path::new("/sys/block").join(device).exists()
+}
+
+pub struct IoMonitor {
+    last_disks: HashMap<String, DiskCounters>,
+    stat: StatReader,
+    last_cpu: Option<CpuTimes>,
+    /// Kept while no clock tick elapsed since `last_cpu`.
+    iowait: f32,
+    last_elapsed: f64,
+}
+
+impl IoMonitor {
+    pub fn new() -> IoMonitor {
+        IoMonitor {
+            last_disks: HashMap::new(),
+            stat: StatReader::new(),
+            last_cpu: None,
+            iowait: 0.0,
+            last_elapsed: 0.0,
+        }
+    }
+
+    /// Computes the rates since the previous sample.
+    pub fn read(&mut self, elapsed: f64) -> IoSample {
+        let period = elapsed - self.last_elapsed;
+        let rate = |current: u64, last: u64| {
+            if period > 0.0 {
+                current.saturating_sub(last) as f64 / period
+            } else {
+                0.0
+            }
+        };
+
+        let disks =
+            parse_diskstats(&std::fs::read_to_string("/proc/diskstats").unwrap_or_default());
+        let mut disk_samples: Vec<DiskSample> = disks
+            .iter()
+            .filter(|(device, _)| is_disk(device))
+            .filter_map(|(device, counters)| {
+                let last = self.last_disks.get(device)?;
+                Some(DiskSample {
+                    device: device.clone(),
+                    read_bytes_rate: rate(counters.read_sectors, last.read_sectors)
+                        * SECTOR_SIZE as f64,
+                    write_bytes_rate: rate(counters.write_sectors, last.write_sectors)
+                        * SECTOR_SIZE as f64,
+                    read_iops: rate(counters.reads, last.reads),
+                    write_iops: rate(counters.writes, last.writes),
+                })
+            })
+            .collect();
+        disk_samples.sort_by(|a, b| a.device.cmp(&b.device));
+
+        let (cpu, _) = self.stat.read();
+        match (cpu, self.last_cpu) {
+            (Some(cpu), Some(last)) if cpu.total > last.total => {
+                self.iowait = (cpu.iowait.saturating_sub(last.iowait) as f64
+                    / (cpu.total - last.total) as f64
+                    * 100.0) as f32;
+                self.last_cpu = Some(cpu);
+            }
+            (Some(_), Some(_)) => {}
+            _ => self.last_cpu = cpu,
+        }
+
+        let sample = IoSample {
+            iowait: self.iowait,
+            disks: disk_samples,
+        };
+
+        self.last_disks = disks;
+        self.last_elapsed = elapsed;
+        sample
+    }
+}
+
+impl Sampler for IoMonitor {
+    fn name(&self) -> &'static str {
+        IO
+    }
+
+    fn sample(&mut self, elapsed: f64) -> serde_json::Value {
+        serde_json::to_value(self.read(elapsed)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diskstats() {
+        let content = concat!(
+            " 259       0 nvme0n1 1000 20 80000 300 2000 40 160000 900 0 1200 1300 0 0 0 0\n",
+            " 259       1 nvme0n1p1 10 0 800 3 20 0 1600 9 0 12 13\n",
+        );
+        let disks = parse_diskstats(content);
+
+        assert_eq!(disks.len(), 2);
+        assert_eq!(
+            disks["nvme0n1"],
+            DiskCounters {
+                reads: 1000,
+                read_sectors: 80000,
+                writes: 2000,
+                write_sectors: 160000,
+            }
+        );
+    }
+}
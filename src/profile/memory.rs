@@ -0,0 +1,161 @@
+//! Memory breakdown sampling from /proc/meminfo and /proc/vmstat, and OOM
+//! kill events of the system or of the build cgroup.
+
+use super::sampler::{Sampler, SamplerContext, MEM};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MemorySample {
+    /// Memory in use system wide, total minus available.
+    pub used: u64,
+    pub available: u64,
+    pub swap_used: u64,
+    pub cache: u64,
+    pub dirty: u64,
+    pub writeback: u64,
+    /// Pages swapped in and out, and OOM kills since the monitoring started.
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub oom_kills: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct MemInfo {
+    total: u64,
+    available: u64,
+    swap_used: u64,
+    cache: u64,
+    dirty: u64,
+    writeback: u64,
+}
+
+/// Reads a `key value` or `key: value kB` field.
+fn field(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next()?.trim_end_matches(':') == key {
+            parts.next()?.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn parse_meminfo(content: &str) -> MemInfo {
+    let kb = |key: &str| field(content, key).unwrap_or(0) * 1024;
+    MemInfo {
+        total: kb("MemTotal"),
+        available: kb("MemAvailable"),
+        swap_used: kb("SwapTotal").saturating_sub(kb("SwapFree")),
+        cache: kb("Cached") + kb("Buffers"),
+        dirty: kb("Dirty"),
+        writeback: kb("Writeback"),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Counters {
+    swap_in: u64,
+    swap_out: u64,
+    oom_kills: u64,
+}
+
+pub struct MemoryMonitor {
+    events: Option<PathBuf>,
+    start: Counters,
+}
+
+impl MemoryMonitor {
+    pub fn new() -> MemoryMonitor {
+        MemoryMonitor {
+            events: None,
+            start: Counters::default(),
+        }
+    }
+
+    fn read_counters(&self) -> Counters {
+        let vmstat = std::fs::read_to_string("/proc/vmstat").unwrap_or_default();
+        let oom_kills = match &self.events {
+            Some(events) => std::fs::read_to_string(events)
+                .ok()
+                .and_then(|content| field(&content, "oom_kill")),
+            None => None,
+        };
+
+        Counters {
+            swap_in: field(&vmstat, "pswpin").unwrap_or(0),
+            swap_out: field(&vmstat, "pswpout").unwrap_or(0),
+            oom_kills: oom_kills
+                .or_else(|| field(&vmstat, "oom_kill"))
+                .unwrap_or(0),
+        }
+    }
+
+    pub fn read(&self) -> MemorySample {
+        let meminfo = parse_meminfo(&std::fs::read_to_string("/proc/meminfo").unwrap_or_default());
+        let counters = self.read_counters();
+
+        MemorySample {
+            used: meminfo.total.saturating_sub(meminfo.available),
+            available: meminfo.available,
+            swap_used: meminfo.swap_used,
+            cache: meminfo.cache,
+            dirty: meminfo.dirty,
+            writeback: meminfo.writeback,
+            swap_in: counters.swap_in.saturating_sub(self.start.swap_in),
+            swap_out: counters.swap_out.saturating_sub(self.start.swap_out),
+            oom_kills: counters.oom_kills.saturating_sub(self.start.oom_kills),
+        }
+    }
+}
+
+impl Sampler for MemoryMonitor {
+    fn name(&self) -> &'static str {
+        MEM
+    }
+
+    /// OOM kills are counted in the build cgroup when there is one, system
+    /// wide otherwise.
+    fn init(&mut self, context: &SamplerContext) {
+        self.events = context
+            .cgroup
+            .as_ref()
+            .map(|cgroup| cgroup.join("memory.events"));
+        self.start = self.read_counters();
+    }
+
+    fn sample(&mut self, _elapsed: f64) -> serde_json::Value {
+        serde_json::to_value(self.read()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let content = "MemTotal:        8000000 kB\n\
+                       MemAvailable:    5000000 kB\n\
+                       Buffers:           10000 kB\n\
+                       Cached:          2000000 kB\n\
+                       SwapTotal:       1000000 kB\n\
+                       SwapFree:         400000 kB\n\
+                       Dirty:              1200 kB\n\
+                       Writeback:            16 kB\n";
+        assert_eq!(
+            parse_meminfo(content),
+            MemInfo {
+                total: 8000000 * 1024,
+                available: 5000000 * 1024,
+                swap_used: 600000 * 1024,
+                cache: 2010000 * 1024,
+                dirty: 1200 * 1024,
+                writeback: 16 * 1024,
+            }
+        );
+        assert_eq!(field("pswpin 12\npswpout 34\n", "pswpout"), Some(34));
+    }
+}
@@ -0,0 +1,441 @@
+use super::sampler::{Sampler, SamplerContext, PROCS};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProcessLifetime {
+    pub pid: u32,
+    pub ppid: u32,
+    pub cmdline: String,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub cpu_time: f64,
+    pub max_rss: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ProcessTreeSample {
+    pub cpu_usage: f32,
+    pub memory_usage: u64,
+    pub pss_usage: u64,
+    /// Bytes read from and written to storage by the tree so far, including
+    /// the processes which have already exited.
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_rate: f64,
+    pub write_rate: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct ProcStat {
+    pid: u32,
+    ppid: u32,
+    utime: u64,
+    stime: u64,
+    starttime: u64,
+    rss: u64,
+}
+
+struct TrackedProcess {
+    starttime: u64,
+    last_cpu_ticks: u64,
+    last_io: (u64, u64),
+    /// I/O of the exited children, which the kernel adds to the counters of
+    /// the parent once they are reaped.
+    reaped_io: (u64, u64),
+    lifetime: ProcessLifetime,
+}
+
+impl TrackedProcess {
+    /// I/O of the process itself, without its reaped children.
+    fn own_io(&self) -> (u64, u64) {
+        (
+            self.last_io.0.saturating_sub(self.reaped_io.0),
+            self.last_io.1.saturating_sub(self.reaped_io.1),
+        )
+    }
+}
+
+/// Follows the descendant tree of the build process by walking /proc on
+/// every sample. Processes living shorter than the sampling interval may
+/// not be observed at all.
+pub struct ProcessTracker {
+    root: Option<u32>,
+    num_cpus: u32,
+    clock_ticks: f64,
+    page_size: u64,
+    uptime_at_start: f64,
+    last_elapsed: f64,
+    active: HashMap<u32, TrackedProcess>,
+    finished: Vec<ProcessLifetime>,
+    finished_io: (u64, u64),
+    last_io: (u64, u64),
+}
+
+impl ProcessTracker {
+    pub fn new() -> ProcessTracker {
+        let clock_ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+        ProcessTracker {
+            root: None,
+            num_cpus: 1,
+            clock_ticks: if clock_ticks > 0 {
+                clock_ticks as f64
+            } else {
+                100.0
+            },
+            page_size: if page_size > 0 {
+                page_size as u64
+            } else {
+                4096
+            },
+            uptime_at_start: 0.0,
+            last_elapsed: 0.0,
+            active: HashMap::new(),
+            finished: Vec::new(),
+            finished_io: (0, 0),
+            last_io: (0, 0),
+        }
+    }
+
+    pub fn read(&mut self, elapsed: f64) -> ProcessTreeSample {
+        let root = match self.root {
+            Some(root) => root,
+            None => return ProcessTreeSample::default(),
+        };
+
+        let stats = read_all_stats();
+        let tree = descendants(root, &stats);
+
+        let mut cpu_ticks = 0;
+        let mut memory_usage = 0;
+        let mut pss_usage = 0;
+        let mut seen = Vec::with_capacity(tree.len());
+
+        for stat in tree {
+            let rss = stat.rss * self.page_size;
+            let ticks = stat.utime + stat.stime;
+            memory_usage += rss;
+            pss_usage += read_pss(stat.pid).unwrap_or(0);
+            seen.push(stat.pid);
+
+            let io = read_io(stat.pid);
+
+            if let Some(tracked) = self.active.get_mut(&stat.pid) {
+                if tracked.starttime == stat.starttime {
+                    cpu_ticks += ticks.saturating_sub(tracked.last_cpu_ticks);
+                    tracked.last_cpu_ticks = ticks;
+                    if let Some(io) = io {
+                        tracked.last_io = io;
+                    }
+                    tracked.lifetime.end_time = elapsed;
+                    tracked.lifetime.cpu_time = ticks as f64 / self.clock_ticks;
+                    tracked.lifetime.max_rss = tracked.lifetime.max_rss.max(rss);
+                    continue;
+                }
+                // pid was reused by a new process
+                let old = self.active.remove(&stat.pid).unwrap();
+                self.finish_process(old);
+            }
+
+            cpu_ticks += ticks;
+            self.active.insert(
+                stat.pid,
+                TrackedProcess {
+                    starttime: stat.starttime,
+                    last_cpu_ticks: ticks,
+                    last_io: io.unwrap_or((0, 0)),
+                    reaped_io: (0, 0),
+                    lifetime: ProcessLifetime {
+                        pid: stat.pid,
+                        ppid: stat.ppid,
+                        cmdline: read_cmdline(stat.pid),
+                        start_time: (stat.starttime as f64 / self.clock_ticks
+                            - self.uptime_at_start)
+                            .max(0.0),
+                        end_time: elapsed,
+                        cpu_time: ticks as f64 / self.clock_ticks,
+                        max_rss: rss,
+                    },
+                },
+            );
+        }
+
+        let mut exited: Vec<u32> = self
+            .active
+            .keys()
+            .filter(|pid| !seen.contains(pid))
+            .copied()
+            .collect();
+        // children first, so their I/O is credited to the parent in time
+        while !exited.is_empty() {
+            let is_parent = |pid: &u32| {
+                exited
+                    .iter()
+                    .any(|other| self.active[other].lifetime.ppid == *pid)
+            };
+            let i = exited.iter().position(|pid| !is_parent(pid)).unwrap_or(0);
+            let tracked = self.active.remove(&exited.swap_remove(i)).unwrap();
+            self.finish_process(tracked);
+        }
+
+        let period = elapsed - self.last_elapsed;
+        self.last_elapsed = elapsed;
+        let rate = |amount: f64| if period > 0.0 { amount / period } else { 0.0 };
+        let cpu_usage =
+            (rate(cpu_ticks as f64 / self.clock_ticks) / self.num_cpus as f64 * 100.0) as f32;
+
+        let (read_bytes, write_bytes) = self
+            .active
+            .values()
+            .map(TrackedProcess::own_io)
+            .fold(self.finished_io, |(read, write), io| {
+                (read + io.0, write + io.1)
+            });
+        let read_rate = rate(read_bytes.saturating_sub(self.last_io.0) as f64);
+        let write_rate = rate(write_bytes.saturating_sub(self.last_io.1) as f64);
+        self.last_io = (read_bytes, write_bytes);
+
+        ProcessTreeSample {
+            cpu_usage,
+            memory_usage,
+            pss_usage,
+            read_bytes,
+            write_bytes,
+            read_rate,
+            write_rate,
+        }
+    }
+
+    fn finish_process(&mut self, tracked: TrackedProcess) {
+        let io = tracked.own_io();
+        self.finished_io.0 += io.0;
+        self.finished_io.1 += io.1;
+        if let Some(parent) = self.active.get_mut(&tracked.lifetime.ppid) {
+            parent.reaped_io.0 += io.0 + tracked.reaped_io.0;
+            parent.reaped_io.1 += io.1 + tracked.reaped_io.1;
+        }
+        self.finished.push(tracked.lifetime);
+    }
+
+    pub fn finish(&mut self) -> Vec<ProcessLifetime> {
+        let mut processes = std::mem::take(&mut self.finished);
+        processes.extend(self.active.drain().map(|(_, tracked)| tracked.lifetime));
+        processes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        processes
+    }
+}
+
+impl Sampler for ProcessTracker {
+    fn name(&self) -> &'static str {
+        PROCS
+    }
+
+    fn init(&mut self, context: &SamplerContext) {
+        self.num_cpus = context.num_cpus.max(1);
+        self.uptime_at_start = read_uptime().unwrap_or(0.0);
+    }
+
+    fn track(&mut self, pid: u32, elapsed: f64) {
+        self.root = Some(pid);
+        self.last_elapsed = elapsed;
+    }
+
+    fn sample(&mut self, elapsed: f64) -> serde_json::Value {
+        serde_json::to_value(self.read(elapsed)).unwrap_or_default()
+    }
+
+    /// Returns the lifetimes of the processes of the build tree.
+    fn finalize(&mut self) -> Option<serde_json::Value> {
+        serde_json::to_value(self.finish()).ok()
+    }
+}
+
+fn parse_stat(content: &str) -> Option<ProcStat> {
+    // the command name may contain spaces and parentheses
+    let comm_end = content.rfind(')')?;
+    let pid = content[..content.find('(')?].trim().parse().ok()?;
+    let fields: Vec<&str> = content[comm_end + 1..].split_whitespace().collect();
+
+    Some(ProcStat {
+        pid,
+        ppid: fields.get(1)?.parse().ok()?,
+        utime: fields.get(11)?.parse().ok()?,
+        stime: fields.get(12)?.parse().ok()?,
+        starttime: fields.get(19)?.parse().ok()?,
+        rss: fields.get(21)?.parse().ok()?,
+    })
+}
+
+fn read_all_stats() -> Vec<ProcStat> {
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("stat")).ok())
+        .filter_map(|content| parse_stat(&content))
+        .collect()
+}
+
+fn descendants(root: u32, stats: &[ProcStat]) -> Vec<&ProcStat> {
+    let mut tree: Vec<&ProcStat> = stats.iter().filter(|stat| stat.pid == root).collect();
+    let mut i = 0;
+
+    while i < tree.len() {
+        let parent = tree[i].pid;
+        tree.extend(stats.iter().filter(|stat| stat.ppid == parent));
+        i += 1;
+    }
+
+    tree
+}
+
+/// Parses the storage `read_bytes` and `write_bytes` of `/proc/<pid>/io`.
+fn parse_io(content: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    Some((field("read_bytes:")?, field("write_bytes:")?))
+}
+
+fn read_io(pid: u32) -> Option<(u64, u64)> {
+    parse_io(&std::fs::read_to_string(format!("/proc/{}/io", pid)).ok()?)
+}
+
+/// Proportional set size in bytes, sharing pages among the build processes
+/// is not counted more than once unlike the RSS.
+fn read_pss(pid: u32) -> Option<u64> {
+    let content = std::fs::read_to_string(format!("/proc/{}/smaps_rollup", pid)).ok()?;
+    let kb = content
+        .lines()
+        .find_map(|line| line.strip_prefix("Pss:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+fn read_cmdline(pid: u32) -> String {
+    match std::fs::read(format!("/proc/{}/cmdline", pid)) {
+        Ok(content) => content
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect::<Vec<String>>()
+            .join(" "),
+        Err(_) => String::new(),
+    }
+}
+
+fn read_uptime() -> Option<f64> {
+    let content = std::fs::read_to_string("/proc/uptime").ok()?;
+    content.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = parse_stat(
+            "4242 (cc1 (x) y) R 4200 4242 4100 0 -1 4194304 1500 0 0 0 \
+             250 30 0 0 20 0 1 0 987654 123456789 3000 18446744073709551615",
+        )
+        .unwrap();
+
+        assert_eq!(
+            stat,
+            ProcStat {
+                pid: 4242,
+                ppid: 4200,
+                utime: 250,
+                stime: 30,
+                starttime: 987654,
+                rss: 3000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_io() {
+        let content = "rchar: 1000\nwchar: 2000\nsyscr: 10\nsyscw: 20\n\
+                       read_bytes: 4096\nwrite_bytes: 8192\ncancelled_write_bytes: 0\n";
+        assert_eq!(parse_io(content), Some((4096, 8192)));
+        assert_eq!(parse_io("rchar: 1000\n"), None);
+    }
+
+    #[test]
+    fn test_reaped_io() {
+        let tracked = |pid, ppid, io| TrackedProcess {
+            starttime: 0,
+            last_cpu_ticks: 0,
+            last_io: io,
+            reaped_io: (0, 0),
+            lifetime: ProcessLifetime {
+                pid,
+                ppid,
+                cmdline: String::new(),
+                start_time: 0.0,
+                end_time: 0.0,
+                cpu_time: 0.0,
+                max_rss: 0,
+            },
+        };
+        let mut tracker = ProcessTracker::new();
+        tracker.active.insert(10, tracked(10, 1, (100, 10)));
+        tracker.active.insert(11, tracked(11, 10, (50, 5)));
+
+        // the kernel adds the I/O of the reaped child to the parent
+        let child = tracker.active.remove(&11).unwrap();
+        tracker.finish_process(child);
+        let parent = tracker.active.get_mut(&10).unwrap();
+        parent.last_io = (150, 15);
+
+        let own = parent.own_io();
+        assert_eq!(own, (100, 10));
+        assert_eq!(
+            (tracker.finished_io.0 + own.0, tracker.finished_io.1 + own.1),
+            (150, 15)
+        );
+    }
+
+    #[test]
+    fn test_descendants() {
+        let stat = |pid, ppid| ProcStat {
+            pid,
+            ppid,
+            utime: 0,
+            stime: 0,
+            starttime: 0,
+            rss: 0,
+        };
+        let stats = vec![
+            stat(1, 0),
+            stat(10, 1),
+            stat(11, 10),
+            stat(12, 11),
+            stat(20, 1),
+        ];
+
+        let tree: Vec<u32> = descendants(10, &stats).iter().map(|s| s.pid).collect();
+
+        assert_eq!(tree, vec![10, 11, 12]);
+    }
+}
@@ -0,0 +1,149 @@
+//! Pressure Stall Information sampling, telling whether tasks were actually
+//! stalled waiting for CPU, memory or I/O.
+
+use super::sampler::{Sampler, SamplerContext, PSI};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PressureSample {
+    /// Share of time in percent some or all tasks were stalled, averaged
+    /// over the last 10 seconds.
+    pub some_avg10: f32,
+    pub full_avg10: f32,
+    /// Stall time in microseconds since the previous sample.
+    pub some_stall: u64,
+    pub full_stall: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct PsiSample {
+    pub cpu: Option<PressureSample>,
+    pub memory: Option<PressureSample>,
+    pub io: Option<PressureSample>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Pressure {
+    some_avg10: f32,
+    some_total: u64,
+    full_avg10: f32,
+    full_total: u64,
+}
+
+fn parse_pressure(content: &str) -> Option<Pressure> {
+    let mut pressure = Pressure::default();
+    let mut found = false;
+
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?;
+        let mut avg10 = None;
+        let mut total = None;
+        for field in fields {
+            match field.split_once('=') {
+                Some(("avg10", value)) => avg10 = value.parse::<f32>().ok(),
+                Some(("total", value)) => total = value.parse::<u64>().ok(),
+                _ => {}
+            }
+        }
+        match (kind, avg10, total) {
+            ("some", Some(avg10), Some(total)) => {
+                pressure.some_avg10 = avg10;
+                pressure.some_total = total;
+                found = true;
+            }
+            // the full line of cpu is all zeros on older kernels
+            ("full", Some(avg10), Some(total)) => {
+                pressure.full_avg10 = avg10;
+                pressure.full_total = total;
+            }
+            _ => {}
+        }
+    }
+
+    if found {
+        Some(pressure)
+    } else {
+        None
+    }
+}
+
+pub struct PsiMonitor {
+    paths: [PathBuf; 3],
+    last: [Option<Pressure>; 3],
+}
+
+impl PsiMonitor {
+    pub fn new() -> PsiMonitor {
+        PsiMonitor {
+            paths: ["cpu", "memory", "io"]
+                .map(|resource| Path::new("/proc/pressure").join(resource)),
+            last: [None; 3],
+        }
+    }
+
+    pub fn read(&mut self) -> PsiSample {
+        let mut samples = [None; 3];
+        for (i, path) in self.paths.iter().enumerate() {
+            let pressure = std::fs::read_to_string(path)
+                .ok()
+                .and_then(|content| parse_pressure(&content));
+            samples[i] = pressure.map(|pressure| {
+                let last = self.last[i].unwrap_or(pressure);
+                PressureSample {
+                    some_avg10: pressure.some_avg10,
+                    full_avg10: pressure.full_avg10,
+                    some_stall: pressure.some_total.saturating_sub(last.some_total),
+                    full_stall: pressure.full_total.saturating_sub(last.full_total),
+                }
+            });
+            self.last[i] = pressure;
+        }
+
+        let [cpu, memory, io] = samples;
+        PsiSample { cpu, memory, io }
+    }
+}
+
+impl Sampler for PsiMonitor {
+    fn name(&self) -> &'static str {
+        PSI
+    }
+
+    /// Reads the pressure of the build cgroup when there is one, the system
+    /// wide pressure otherwise.
+    fn init(&mut self, context: &SamplerContext) {
+        if let Some(cgroup) = &context.cgroup {
+            self.paths = ["cpu", "memory", "io"]
+                .map(|resource| cgroup.join(format!("{}.pressure", resource)));
+        }
+        self.read();
+    }
+
+    fn sample(&mut self, _elapsed: f64) -> serde_json::Value {
+        serde_json::to_value(self.read()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pressure() {
+        let content = "some avg10=5.98 avg60=4.09 avg300=3.43 total=63817313\n\
+                       full avg10=1.50 avg60=0.00 avg300=0.00 total=1200\n";
+        assert_eq!(
+            parse_pressure(content),
+            Some(Pressure {
+                some_avg10: 5.98,
+                some_total: 63817313,
+                full_avg10: 1.5,
+                full_total: 1200,
+            })
+        );
+        assert_eq!(parse_pressure(""), None);
+    }
+}
@@ -1,49 +1,31 @@
-use libc::{getrusage, rusage, RUSAGE_CHILDREN};
-use serde::Serialize;
-use std::{ops::Sub, time::Duration};
+use libc::rusage;
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::{Add, Sub},
+    time::Duration,
+};
 
-#[derive(Serialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
 pub struct Rusage {
-    user_time: Duration,
-    system_time: Duration,
-    max_rss: u64,
-    ixrss: u64,
-    idrss: u64,
-    isrss: u64,
-    minflt: u64,
-    majflt: u64,
-    nswap: u64,
-    inblock: u64,
-    oublock: u64,
-    msgsnd: u64,
-    msgrcv: u64,
-    nsignals: u64,
-    nvcsw: u64,
-    nivcsw: u64,
+    pub user_time: Duration,
+    pub system_time: Duration,
+    pub max_rss: u64,
+    pub ixrss: u64,
+    pub idrss: u64,
+    pub isrss: u64,
+    pub minflt: u64,
+    pub majflt: u64,
+    pub nswap: u64,
+    pub inblock: u64,
+    pub oublock: u64,
+    pub msgsnd: u64,
+    pub msgrcv: u64,
+    pub nsignals: u64,
+    pub nvcsw: u64,
+    pub nivcsw: u64,
 }
 
 impl Rusage {
-    pub fn default() -> Rusage {
-        Rusage {
-            user_time: Duration::new(0, 0),
-            system_time: Duration::new(0, 0),
-            max_rss: 0,
-            ixrss: 0,
-            idrss: 0,
-            isrss: 0,
-            minflt: 0,
-            majflt: 0,
-            nswap: 0,
-            inblock: 0,
-            oublock: 0,
-            msgsnd: 0,
-            msgrcv: 0,
-            nsignals: 0,
-            nvcsw: 0,
-            nivcsw: 0,
-        }
-    }
-
     pub fn from_libc_rusage(rusage: &rusage) -> Rusage {
         Rusage {
             user_time: Duration::new(
@@ -72,31 +54,55 @@ impl Rusage {
     }
 }
 
+/// Field-wise saturating difference of cumulative counters. `max_rss` is a
+/// peak rather than a counter, so the larger of the two is kept.
 impl Sub for Rusage {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        if self <= rhs {
-            Rusage::default()
-        } else {
-            Rusage {
-                user_time: self.user_time - rhs.user_time,
-                system_time: self.system_time - rhs.system_time,
-                max_rss: self.max_rss - rhs.max_rss,
-                ixrss: self.ixrss - rhs.ixrss,
-                idrss: self.idrss - rhs.idrss,
-                isrss: self.isrss - rhs.isrss,
-                minflt: self.minflt - rhs.minflt,
-                majflt: self.majflt - rhs.majflt,
-                nswap: self.nswap - rhs.nswap,
-                inblock: self.inblock - rhs.inblock,
-                oublock: self.oublock - rhs.oublock,
-                msgsnd: self.msgsnd - rhs.msgsnd,
-                msgrcv: self.msgrcv - rhs.msgrcv,
-                nsignals: self.nsignals - rhs.nsignals,
-                nvcsw: self.nvcsw - rhs.nvcsw,
-                nivcsw: self.nivcsw - rhs.nivcsw,
-            }
+        Rusage {
+            user_time: self.user_time.saturating_sub(rhs.user_time),
+            system_time: self.system_time.saturating_sub(rhs.system_time),
+            max_rss: self.max_rss.max(rhs.max_rss),
+            ixrss: self.ixrss.saturating_sub(rhs.ixrss),
+            idrss: self.idrss.saturating_sub(rhs.idrss),
+            isrss: self.isrss.saturating_sub(rhs.isrss),
+            minflt: self.minflt.saturating_sub(rhs.minflt),
+            majflt: self.majflt.saturating_sub(rhs.majflt),
+            nswap: self.nswap.saturating_sub(rhs.nswap),
+            inblock: self.inblock.saturating_sub(rhs.inblock),
+            oublock: self.oublock.saturating_sub(rhs.oublock),
+            msgsnd: self.msgsnd.saturating_sub(rhs.msgsnd),
+            msgrcv: self.msgrcv.saturating_sub(rhs.msgrcv),
+            nsignals: self.nsignals.saturating_sub(rhs.nsignals),
+            nvcsw: self.nvcsw.saturating_sub(rhs.nvcsw),
+            nivcsw: self.nivcsw.saturating_sub(rhs.nivcsw),
+        }
+    }
+}
+
+/// Field-wise saturating sum, keeping the larger `max_rss`.
+impl Add for Rusage {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Rusage {
+            user_time: self.user_time.saturating_add(rhs.user_time),
+            system_time: self.system_time.saturating_add(rhs.system_time),
+            max_rss: self.max_rss.max(rhs.max_rss),
+            ixrss: self.ixrss.saturating_add(rhs.ixrss),
+            idrss: self.idrss.saturating_add(rhs.idrss),
+            isrss: self.isrss.saturating_add(rhs.isrss),
+            minflt: self.minflt.saturating_add(rhs.minflt),
+            majflt: self.majflt.saturating_add(rhs.majflt),
+            nswap: self.nswap.saturating_add(rhs.nswap),
+            inblock: self.inblock.saturating_add(rhs.inblock),
+            oublock: self.oublock.saturating_add(rhs.oublock),
+            msgsnd: self.msgsnd.saturating_add(rhs.msgsnd),
+            msgrcv: self.msgrcv.saturating_add(rhs.msgrcv),
+            nsignals: self.nsignals.saturating_add(rhs.nsignals),
+            nvcsw: self.nvcsw.saturating_add(rhs.nvcsw),
+            nivcsw: self.nivcsw.saturating_add(rhs.nivcsw),
         }
     }
 }
@@ -176,8 +182,8 @@ impl PartialEq for Rusage {
     }
 }
 
-fn get_rusage() -> rusage {
-    let mut rusage = rusage {
+fn empty_rusage() -> rusage {
+    rusage {
         ru_utime: libc::timeval {
             tv_sec: 0,
             tv_usec: 0,
@@ -200,15 +206,24 @@ fn get_rusage() -> rusage {
         ru_nsignals: 0,
         ru_nvcsw: 0,
         ru_nivcsw: 0,
-    };
-    unsafe {
-        getrusage(RUSAGE_CHILDREN, &mut rusage);
     }
-    rusage
 }
 
-pub fn get_process_rusage() -> Rusage {
-    Rusage::from_libc_rusage(&get_rusage())
+/// Reaps the child with the given pid, returning its raw wait status and the
+/// resource usage of the child and all of its waited-for descendants.
+pub fn wait4(pid: u32) -> std::io::Result<(i32, Rusage)> {
+    let mut status = 0;
+    let mut rusage = empty_rusage();
+    loop {
+        let ret = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut rusage) };
+        if ret >= 0 {
+            return Ok((status, Rusage::from_libc_rusage(&rusage)));
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -292,7 +307,7 @@ mod tests {
 
         assert_eq!(c.user_time, std::time::Duration::from_secs(1));
         assert_eq!(c.system_time, std::time::Duration::from_secs(1));
-        assert_eq!(c.max_rss, 1);
+        assert_eq!(c.max_rss, 2);
         assert_eq!(c.ixrss, 1);
         assert_eq!(c.idrss, 1);
         assert_eq!(c.isrss, 1);
@@ -351,7 +366,7 @@ mod tests {
 
         assert_eq!(c.user_time, std::time::Duration::from_secs(0));
         assert_eq!(c.system_time, std::time::Duration::from_secs(0));
-        assert_eq!(c.max_rss, 0);
+        assert_eq!(c.max_rss, 2);
         assert_eq!(c.ixrss, 0);
         assert_eq!(c.idrss, 0);
         assert_eq!(c.isrss, 0);
@@ -366,4 +381,23 @@ mod tests {
         assert_eq!(c.nvcsw, 0);
         assert_eq!(c.nivcsw, 0);
     }
+
+    #[test]
+    fn test_add() {
+        let mut a = Rusage::default();
+        let mut b = Rusage::default();
+
+        a.user_time = std::time::Duration::from_secs(1);
+        a.max_rss = 3;
+        a.minflt = u64::MAX;
+        b.user_time = std::time::Duration::from_secs(2);
+        b.max_rss = 2;
+        b.minflt = 1;
+
+        let c = a + b;
+
+        assert_eq!(c.user_time, std::time::Duration::from_secs(3));
+        assert_eq!(c.max_rss, 3);
+        assert_eq!(c.minflt, u64::MAX);
+    }
 }
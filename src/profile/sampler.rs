@@ -0,0 +1,111 @@
+//! Pluggable sources of the values stored in the data points, every sampler
+//! adds its values under its own name.
+
+use super::{cgroup, cpu, io, memory, procs, psi};
+use crate::error::{Error, Result};
+use std::path::PathBuf;
+
+pub const CPU: &str = "cpu";
+pub const MEM: &str = "mem";
+pub const IO: &str = "io";
+pub const PSI: &str = "psi";
+pub const PROCS: &str = "procs";
+pub const CGROUP: &str = "cgroup";
+
+/// Samplers selectable from the command line, the cgroup sampler is always
+/// enabled when the build runs in a cgroup.
+pub const NAMES: [&str; 5] = [CPU, MEM, IO, PSI, PROCS];
+
+pub struct SamplerContext {
+    pub num_cpus: u32,
+    /// Cgroup the build runs in, if any.
+    pub cgroup: Option<PathBuf>,
+}
+
+pub trait Sampler: Send {
+    fn name(&self) -> &'static str;
+
+    /// Called once when the monitoring starts, before the first sample.
+    fn init(&mut self, _context: &SamplerContext) {}
+
+    /// Called when the build process is spawned.
+    fn track(&mut self, _pid: u32, _elapsed: f64) {}
+
+    /// Returns the values at `elapsed` seconds since the monitoring started.
+    fn sample(&mut self, elapsed: f64) -> serde_json::Value;
+
+    /// Returns the values aggregated over the whole run, if any.
+    fn finalize(&mut self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// Parses a comma separated list of sampler names.
+pub fn parse_names(list: &str) -> Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+    for name in list
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if !NAMES.contains(&name) {
+            return Err(Error::InvalidArgument(format!(
+                "unknown sampler \"{}\", expected one of {}",
+                name,
+                NAMES.join(", ")
+            )));
+        }
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    if names.is_empty() {
+        return Err(Error::invalid_argument("no samplers given"));
+    }
+    Ok(names)
+}
+
+/// Creates the samplers in a fixed order, so the values read at the same
+/// time are as close as possible to the sample time.
+pub fn create(names: &[String], cgroup: bool) -> Vec<Box<dyn Sampler>> {
+    let enabled = |name: &str| names.is_empty() || names.iter().any(|n| n == name);
+    let mut samplers: Vec<Box<dyn Sampler>> = Vec::new();
+
+    if cgroup {
+        samplers.push(Box::new(cgroup::CgroupSampler::new()));
+    }
+    if enabled(PSI) {
+        samplers.push(Box::new(psi::PsiMonitor::new()));
+    }
+    if enabled(CPU) {
+        samplers.push(Box::new(cpu::CpuSampler::new()));
+    }
+    if enabled(MEM) {
+        samplers.push(Box::new(memory::MemoryMonitor::new()));
+    }
+    if enabled(PROCS) {
+        samplers.push(Box::new(procs::ProcessTracker::new()));
+    }
+    if enabled(IO) {
+        samplers.push(Box::new(io::IoMonitor::new()));
+    }
+    samplers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(parse_names("cpu, mem,cpu").unwrap(), vec!["cpu", "mem"]);
+        assert!(parse_names("cpu,gpu").is_err());
+        assert!(parse_names(",").is_err());
+
+        let names: Vec<&str> = create(&parse_names("procs,cpu").unwrap(), true)
+            .iter()
+            .map(|sampler| sampler.name())
+            .collect();
+        assert_eq!(names, vec![CGROUP, CPU, PROCS]);
+    }
+}
@@ -0,0 +1,105 @@
+//! Sampling at absolute deadlines, so the sampling cost does not add up to
+//! the period, and the measured timing accuracy of the samples.
+
+use crate::stats::Statistics;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Deadlines at `start + n * interval`. Deadlines which already passed when
+/// a sample was late are skipped instead of sampling in a burst.
+pub struct Schedule {
+    start: Instant,
+    interval: Duration,
+    tick: u32,
+    missed: u32,
+}
+
+impl Schedule {
+    pub fn new(start: Instant, interval: Duration) -> Schedule {
+        Schedule {
+            start,
+            interval,
+            tick: 0,
+            missed: 0,
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.start + self.interval * self.tick
+    }
+
+    /// Advances to the first deadline after `now`.
+    pub fn advance(&mut self, now: Instant) -> Instant {
+        self.tick += 1;
+        let deadline = self.deadline();
+        if now > deadline {
+            let behind = ((now - deadline).as_nanos() / self.interval.as_nanos().max(1)) as u32;
+            self.tick += behind + 1;
+            self.missed += behind + 1;
+        }
+        self.deadline()
+    }
+
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+/// Timing accuracy of the samples of a run, in seconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SamplingStats {
+    pub interval: f64,
+    pub samples: u32,
+    /// Deadlines skipped because a sample took longer than the interval.
+    pub missed: u32,
+    /// Delay of the sample start after its deadline.
+    pub jitter: Statistics,
+    /// Time spent reading all the samplers.
+    pub overhead: Statistics,
+}
+
+impl SamplingStats {
+    pub fn new(
+        interval: Duration,
+        missed: u32,
+        jitter: &[f64],
+        overhead: &[f64],
+    ) -> Option<SamplingStats> {
+        Some(SamplingStats {
+            interval: interval.as_secs_f64(),
+            samples: jitter.len() as u32,
+            missed,
+            jitter: Statistics::from_samples(jitter)?,
+            overhead: Statistics::from_samples(overhead)?,
+        })
+    }
+
+    /// Share of the interval spent sampling, in percent.
+    pub fn load(&self) -> f64 {
+        self.overhead.mean / self.interval * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(10);
+        let mut schedule = Schedule::new(start, interval);
+
+        assert_eq!(schedule.deadline(), start);
+        assert_eq!(
+            schedule.advance(start + Duration::from_millis(2)),
+            start + interval
+        );
+        // the sample at 10 ms took until 35 ms, 20 and 30 ms are skipped
+        assert_eq!(
+            schedule.advance(start + Duration::from_millis(35)),
+            start + interval * 4
+        );
+        assert_eq!(schedule.missed(), 2);
+    }
+}
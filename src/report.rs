@@ -1,12 +1,85 @@
+use super::error::{Error, Result};
 use super::profile;
-use serde::Serialize;
+use super::sequence;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 
-#[derive(Serialize)]
+pub mod cli;
+pub mod csv;
+pub mod html;
+pub mod metadata;
+pub mod scaling;
+pub mod stream;
+pub mod summary;
+pub mod trace;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    /// Streamed while profiling instead of saved at the end.
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// Parses a comma separated list of formats.
+    pub fn parse_list(list: &str) -> Result<Vec<OutputFormat>> {
+        let mut formats = Vec::new();
+        for name in list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let format = match name {
+                "json" => OutputFormat::Json,
+                "csv" => OutputFormat::Csv,
+                "ndjson" => OutputFormat::Ndjson,
+                _ => {
+                    return Err(Error::InvalidArgument(format!(
+                        "unknown output format \"{}\"",
+                        name
+                    )))
+                }
+            };
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+        if formats.is_empty() {
+            return Err(Error::invalid_argument("no output format given"));
+        }
+        Ok(formats)
+    }
+
+    /// Files written for the report at `base`, a path without extension.
+    pub fn paths(&self, base: &str) -> Vec<String> {
+        match self {
+            OutputFormat::Json => vec![format!("{}.json", base)],
+            OutputFormat::Csv => vec![
+                format!("{}-datapoints.csv", base),
+                format!("{}-results.csv", base),
+            ],
+            OutputFormat::Ndjson => vec![format!("{}.ndjson", base)],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Report {
     pub system_info: profile::info::SystemInfo,
     pub profile_settings: profile::ProfileSettings,
     pub profile_results: Vec<profile::ProfileResult>,
+    #[serde(default)]
+    pub summaries: Vec<summary::JobsSummary>,
+    /// Memory of every job count, including the ones where all the runs
+    /// failed and which have no summary.
+    #[serde(default)]
+    pub memory: Vec<(u32, summary::MemorySummary)>,
+    pub scaling: Option<scaling::Scaling>,
+    pub sequence: Option<sequence::SequenceInfo>,
+    pub failure: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<metadata::Metadata>,
 }
 
 impl Report {
@@ -18,15 +91,132 @@ impl Report {
             system_info: (*system_info).clone(),
             profile_settings: (*settings).clone(),
             profile_results: Vec::new(),
+            summaries: Vec::new(),
+            memory: Vec::new(),
+            scaling: None,
+            sequence: None,
+            failure: None,
+            metadata: None,
         }
     }
 
+    /// Loads a pretty report or a report stream.
+    pub fn load(path: &str) -> Result<Report> {
+        let content = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        if stream::is_stream(&content) {
+            return stream::load(&content);
+        }
+        Ok(serde_json::from_str(&content)?)
+    }
+
     pub fn add_result(&mut self, result: profile::ProfileResult) {
         self.profile_results.push(result);
     }
 
-    pub fn save(&self) {
-        let file = File::create("report.json").unwrap();
-        serde_json::to_writer_pretty(file, self).unwrap();
+    pub fn summarize(&mut self) {
+        self.summaries = summary::summarize(&self.profile_results);
+        self.memory = summary::memory_by_jobs(&self.profile_results);
+        self.scaling = scaling::analyze(&self.summaries, self.system_info.total_memory);
+    }
+
+    pub fn print_summary(&self) {
+        summary::print(&self.summaries, &self.memory, &self.profile_results);
+        if let Some(scaling) = &self.scaling {
+            println!();
+            scaling::print(scaling);
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    pub fn save_trace(&self, path: &str) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        Ok(serde_json::to_writer(file, &trace::to_trace(self))?)
+    }
+
+    pub fn save_html(&self, path: &str) -> Result<()> {
+        std::fs::write(path, html::to_html(self)).map_err(|e| Error::io(path, e))
+    }
+
+    /// Writes `<prefix>-datapoints.csv` and `<prefix>-results.csv`, returns
+    /// their paths.
+    pub fn save_csv(&self, prefix: &str) -> Result<Vec<String>> {
+        let paths = OutputFormat::Csv.paths(prefix);
+        for (path, content) in paths
+            .iter()
+            .zip([csv::datapoints(self), csv::results(self)])
+        {
+            std::fs::write(path, content).map_err(|e| Error::io(path, e))?;
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::ProfileResult;
+    use std::time::Duration;
+
+    #[test]
+    fn test_linear_scaling_round_trip() {
+        let info = profile::info::get_system_info();
+        let settings =
+            profile::ProfileSettings::new(Duration::from_secs(1), Duration::ZERO, Duration::ZERO);
+        let mut report = Report::new(&info, &settings);
+        // super-linear speedup, the fitted serial time clamps to zero
+        for (jobs, elapsed_time) in [(1, 0.9), (2, 0.4)] {
+            report.add_result(ProfileResult {
+                elapsed_time,
+                jobs,
+                ..Default::default()
+            });
+        }
+        report.summarize();
+        assert_eq!(report.scaling.as_ref().unwrap().max_speedup, None);
+
+        let path = std::env::temp_dir().join(format!("bsprof-report-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        report.save(path).unwrap();
+        let loaded = Report::load(path);
+        std::fs::remove_file(path).unwrap();
+
+        let scaling = loaded.unwrap().scaling.unwrap();
+        assert_eq!(scaling.max_speedup, None);
+        assert_eq!(scaling.jobs.len(), 2);
+    }
+
+    #[test]
+    fn test_failed_jobs_memory_saved() {
+        let info = profile::info::get_system_info();
+        let settings =
+            profile::ProfileSettings::new(Duration::from_secs(1), Duration::ZERO, Duration::ZERO);
+        let mut report = Report::new(&info, &settings);
+        let mut datapoint = profile::ProfileDatapoint::default();
+        datapoint.samples.insert(
+            profile::sampler::MEM.to_string(),
+            serde_json::json!({"oom_kills": 2}),
+        );
+        report.add_result(ProfileResult {
+            jobs: 16,
+            status: profile::RunStatus::Signaled { signal: 9 },
+            datapoints: vec![datapoint],
+            ..Default::default()
+        });
+        report.summarize();
+        assert!(report.summaries.is_empty());
+
+        let path = std::env::temp_dir().join(format!("bsprof-memory-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        report.save(path).unwrap();
+        let loaded = Report::load(path);
+        std::fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.memory.len(), 1);
+        assert_eq!((loaded.memory[0].0, loaded.memory[0].1.oom_kills), (16, 2));
     }
 }
@@ -0,0 +1,84 @@
+//! The `report` subcommand, rendering saved reports.
+
+use super::Report;
+use crate::error::{Error, Result};
+
+pub struct ReportArgs {
+    pub report: String,
+    pub html: Option<String>,
+    pub csv: Option<String>,
+    pub consolidate: Option<String>,
+}
+
+impl ReportArgs {
+    pub fn parse_from(cmd_line_args: &[String]) -> Result<ReportArgs> {
+        let cmd = clap::Command::new("report")
+            .about("Render a saved report")
+            .arg(
+                clap::Arg::new("report")
+                    .required(true)
+                    .help("Report to render"),
+            )
+            .arg(
+                clap::Arg::new("html")
+                    .long("html")
+                    .required(false)
+                    .help("Save self-contained HTML report to file"),
+            )
+            .arg(
+                clap::Arg::new("consolidate")
+                    .long("consolidate")
+                    .required(false)
+                    .help("Save a report stream as a pretty JSON report to file"),
+            )
+            .arg(
+                clap::Arg::new("csv")
+                    .long("csv")
+                    .required(false)
+                    .help("Save CSV files of the data points and runs with the path prefix"),
+            );
+        let m = cmd.get_matches_from(cmd_line_args);
+
+        let html = m.get_one::<String>("html").cloned();
+        let csv = m.get_one::<String>("csv").cloned();
+        let consolidate = m.get_one::<String>("consolidate").cloned();
+        if html.is_none() && csv.is_none() && consolidate.is_none() {
+            return Err(Error::invalid_argument("no output format specified"));
+        }
+
+        Ok(ReportArgs {
+            report: m.get_one::<String>("report").unwrap().to_owned(),
+            html,
+            csv,
+            consolidate,
+        })
+    }
+}
+
+pub fn run(args: &ReportArgs) -> Result<()> {
+    let report = Report::load(&args.report)?;
+
+    if let Some(path) = &args.consolidate {
+        println!(
+            "Saving report with {} runs to {}",
+            report.profile_results.len(),
+            path
+        );
+        if let Some(failure) = &report.failure {
+            println!("Report is incomplete: {}", failure);
+        }
+        report.save(path)?;
+    }
+
+    if let Some(html) = &args.html {
+        println!("Saving HTML report to {}", html);
+        report.save_html(html)?;
+    }
+    if let Some(prefix) = &args.csv {
+        for path in report.save_csv(prefix)? {
+            println!("Saved CSV to {}", path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,240 @@
+//! Flat CSV export for spreadsheets and data frames: one row per data point
+//! and one row per run.
+
+use super::{summary, Report};
+use crate::profile::rusage::Rusage;
+
+const RUSAGE_COLUMNS: [&str; 16] = [
+    "user_time",
+    "system_time",
+    "max_rss",
+    "ixrss",
+    "idrss",
+    "isrss",
+    "minflt",
+    "majflt",
+    "nswap",
+    "inblock",
+    "oublock",
+    "msgsnd",
+    "msgrcv",
+    "nsignals",
+    "nvcsw",
+    "nivcsw",
+];
+
+/// Quotes a field containing a separator, a quote or a line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn push_row(csv: &mut String, fields: &[String]) {
+    let row: Vec<String> = fields.iter().map(|field| escape(field)).collect();
+    csv.push_str(&row.join(","));
+    csv.push('\n');
+}
+
+/// Times in seconds, sizes and counters as reported by the kernel.
+fn rusage_fields(rusage: &Rusage) -> [String; 16] {
+    [
+        rusage.user_time.as_secs_f64().to_string(),
+        rusage.system_time.as_secs_f64().to_string(),
+        rusage.max_rss.to_string(),
+        rusage.ixrss.to_string(),
+        rusage.idrss.to_string(),
+        rusage.isrss.to_string(),
+        rusage.minflt.to_string(),
+        rusage.majflt.to_string(),
+        rusage.nswap.to_string(),
+        rusage.inblock.to_string(),
+        rusage.oublock.to_string(),
+        rusage.msgsnd.to_string(),
+        rusage.msgrcv.to_string(),
+        rusage.nsignals.to_string(),
+        rusage.nvcsw.to_string(),
+        rusage.nivcsw.to_string(),
+    ]
+}
+
+/// One row per data point, the run id is the index of the run in the report.
+/// Values of disabled samplers are left empty.
+pub fn datapoints(report: &Report) -> String {
+    let cores = report
+        .profile_results
+        .iter()
+        .flat_map(|r| r.datapoints.iter().filter_map(|d| d.cpu()))
+        .map(|cpu| cpu.cores.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut csv = String::new();
+    let mut header: Vec<String> = [
+        "run_id",
+        "jobs",
+        "run",
+        "elapsed",
+        "cpu_usage",
+        "memory_usage",
+        "build_cpu_usage",
+        "build_memory_usage",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+    header.extend((0..cores).map(|core| format!("cpu{}", core)));
+    push_row(&mut csv, &header);
+
+    for (id, result) in report.profile_results.iter().enumerate() {
+        for datapoint in &result.datapoints {
+            let cpu = datapoint.cpu();
+            let mem = datapoint.mem();
+            let build = datapoint.procs();
+            let mut row = vec![
+                id.to_string(),
+                result.jobs.to_string(),
+                result.run.to_string(),
+                datapoint.elapsed.to_string(),
+                cpu.as_ref().map_or(String::new(), |c| c.usage.to_string()),
+                mem.map_or(String::new(), |m| m.used.to_string()),
+                build.map_or(String::new(), |b| b.cpu_usage.to_string()),
+                build.map_or(String::new(), |b| b.memory_usage.to_string()),
+            ];
+            row.extend((0..cores).map(|core| {
+                cpu.as_ref()
+                    .and_then(|c| c.cores.get(core))
+                    .map_or(String::new(), |usage| usage.to_string())
+            }));
+            push_row(&mut csv, &row);
+        }
+    }
+    csv
+}
+
+/// One row per run with the flattened rusage of the build, and whether it
+/// swapped or had OOM kills, empty without memory samples.
+pub fn results(report: &Report) -> String {
+    let mut csv = String::new();
+    let mut header: Vec<String> = ["run_id", "jobs", "run", "status", "elapsed_time"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    header.extend(RUSAGE_COLUMNS.iter().map(|column| column.to_string()));
+    header.extend(["swapping".to_string(), "oom_kills".to_string()]);
+    push_row(&mut csv, &header);
+
+    for (id, result) in report.profile_results.iter().enumerate() {
+        let mut row = vec![
+            id.to_string(),
+            result.jobs.to_string(),
+            result.run.to_string(),
+            result.status.to_string(),
+            result.elapsed_time.to_string(),
+        ];
+        row.extend(rusage_fields(&result.rusage));
+        let memory = summary::run_memory(result);
+        row.push(memory.map_or(String::new(), |m| m.swapping.to_string()));
+        row.push(memory.map_or(String::new(), |m| m.oom_kills.to_string()));
+        push_row(&mut csv, &row);
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{sampler, ProfileDatapoint, ProfileResult, ProfileSettings, RunStatus};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("exit code 2"), "exit code 2");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    fn report() -> Report {
+        let settings = ProfileSettings::new(Duration::from_secs(1), Duration::ZERO, Duration::ZERO);
+        let mut report = Report::new(&crate::profile::info::get_system_info(), &settings);
+
+        let mut datapoint = ProfileDatapoint {
+            elapsed: 1.5,
+            ..Default::default()
+        };
+        datapoint.samples.insert(
+            sampler::CPU.to_string(),
+            json!({"usage": 50.0, "cores": [40.0, 60.0]}),
+        );
+        datapoint
+            .samples
+            .insert(sampler::MEM.to_string(), json!({"used": 1000}));
+        report.profile_results.push(ProfileResult {
+            elapsed_time: 10.0,
+            jobs: 4,
+            rusage: Rusage {
+                user_time: Duration::from_millis(1500),
+                max_rss: 2048,
+                ..Default::default()
+            },
+            datapoints: vec![datapoint],
+            ..Default::default()
+        });
+
+        // the cpu sampler is disabled in the second run
+        let mut datapoint = ProfileDatapoint {
+            elapsed: 0.5,
+            ..Default::default()
+        };
+        datapoint.samples.insert(
+            sampler::MEM.to_string(),
+            json!({"used": 2000, "oom_kills": 1}),
+        );
+        report.profile_results.push(ProfileResult {
+            elapsed_time: 2.5,
+            jobs: 8,
+            run: 1,
+            status: RunStatus::Failed { code: 2 },
+            datapoints: vec![datapoint],
+            ..Default::default()
+        });
+        report
+    }
+
+    #[test]
+    fn test_datapoints() {
+        let csv = datapoints(&report());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "run_id,jobs,run,elapsed,cpu_usage,memory_usage,build_cpu_usage,build_memory_usage,cpu0,cpu1",
+                "0,4,0,1.5,50,1000,,,40,60",
+                "1,8,1,0.5,,2000,,,,",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_results() {
+        let csv = results(&report());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "run_id,jobs,run,status,elapsed_time,user_time,system_time,max_rss,ixrss,idrss,isrss,\
+             minflt,majflt,nswap,inblock,oublock,msgsnd,msgrcv,nsignals,nvcsw,nivcsw,swapping,oom_kills"
+        );
+        assert_eq!(
+            lines[1],
+            "0,4,0,success,10,1.5,0,2048,0,0,0,0,0,0,0,0,0,0,0,0,0,false,0"
+        );
+        assert_eq!(
+            lines[2],
+            "1,8,1,exit code 2,2.5,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,false,1"
+        );
+    }
+}
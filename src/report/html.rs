@@ -0,0 +1,420 @@
+//! Self-contained HTML report with inline SVG charts.
+
+use super::{summary, Report};
+use crate::profile::{procs::ProcessTreeSample, ProfileResult};
+use std::fmt::Write;
+
+const CHART_WIDTH: f64 = 900.0;
+const CHART_HEIGHT: f64 = 420.0;
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_RIGHT: f64 = 180.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 50.0;
+
+const PALETTE: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+struct Series {
+    name: String,
+    color: String,
+    dashed: bool,
+    points: Vec<(f64, f64)>,
+}
+
+impl Series {
+    fn new(name: &str, color: &str, points: Vec<(f64, f64)>) -> Series {
+        Series {
+            name: name.to_string(),
+            color: color.to_string(),
+            dashed: false,
+            points,
+        }
+    }
+
+    fn dashed(mut self) -> Series {
+        self.dashed = true;
+        self
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns evenly spaced "round" tick values covering `[min, max]`.
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+    let range = max - min;
+    if range <= 0.0 || !range.is_finite() {
+        return vec![min];
+    }
+    let raw_step = range / 5.0;
+    let magnitude = 10f64.powf(raw_step.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= raw_step)
+        .unwrap_or(magnitude * 10.0);
+
+    let mut ticks = Vec::new();
+    let mut tick = (min / step).ceil() * step;
+    while tick <= max + step * 1e-9 {
+        ticks.push(tick);
+        tick += step;
+    }
+    ticks
+}
+
+fn format_tick(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn line_chart(title: &str, x_label: &str, y_label: &str, series: &[Series]) -> String {
+    let points = series.iter().flat_map(|s| s.points.iter());
+    let (mut x_min, mut x_max, mut y_max) = (f64::INFINITY, f64::NEG_INFINITY, 0.0f64);
+    for (x, y) in points {
+        x_min = x_min.min(*x);
+        x_max = x_max.max(*x);
+        y_max = y_max.max(*y);
+    }
+    if !x_min.is_finite() {
+        (x_min, x_max) = (0.0, 1.0);
+    }
+    if x_max <= x_min {
+        x_max = x_min + 1.0;
+    }
+    if y_max <= 0.0 {
+        y_max = 1.0;
+    }
+    let y_ticks = ticks(0.0, y_max);
+    let y_max = y_ticks.last().copied().unwrap_or(y_max).max(y_max);
+
+    let plot_width = CHART_WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = CHART_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let sx = |x: f64| MARGIN_LEFT + (x - x_min) / (x_max - x_min) * plot_width;
+    let sy = |y: f64| MARGIN_TOP + plot_height - y / y_max * plot_height;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\
+         <text x=\"{tx}\" y=\"24\" text-anchor=\"middle\" class=\"title\">{title}</text>",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT,
+        tx = MARGIN_LEFT + plot_width / 2.0,
+        title = escape(title)
+    );
+
+    for tick in &y_ticks {
+        let _ = write!(
+            svg,
+            "<line x1=\"{x1}\" x2=\"{x2}\" y1=\"{y:.1}\" y2=\"{y:.1}\" class=\"grid\"/>\
+             <text x=\"{tx}\" y=\"{ty:.1}\" text-anchor=\"end\">{label}</text>",
+            x1 = MARGIN_LEFT,
+            x2 = MARGIN_LEFT + plot_width,
+            y = sy(*tick),
+            tx = MARGIN_LEFT - 6.0,
+            ty = sy(*tick) + 4.0,
+            label = format_tick(*tick)
+        );
+    }
+    for tick in ticks(x_min, x_max) {
+        let _ = write!(
+            svg,
+            "<line x1=\"{x:.1}\" x2=\"{x:.1}\" y1=\"{y1}\" y2=\"{y2}\" class=\"grid\"/>\
+             <text x=\"{x:.1}\" y=\"{ty}\" text-anchor=\"middle\">{label}</text>",
+            x = sx(tick),
+            y1 = MARGIN_TOP,
+            y2 = MARGIN_TOP + plot_height,
+            ty = MARGIN_TOP + plot_height + 16.0,
+            label = format_tick(tick)
+        );
+    }
+    let _ = write!(
+        svg,
+        "<rect x=\"{x}\" y=\"{y}\" width=\"{w}\" height=\"{h}\" class=\"frame\"/>\
+         <text x=\"{lx}\" y=\"{ly}\" text-anchor=\"middle\">{x_label}</text>\
+         <text transform=\"translate(16 {ty}) rotate(-90)\" text-anchor=\"middle\">{y_label}</text>",
+        x = MARGIN_LEFT,
+        y = MARGIN_TOP,
+        w = plot_width,
+        h = plot_height,
+        lx = MARGIN_LEFT + plot_width / 2.0,
+        ly = CHART_HEIGHT - 10.0,
+        ty = MARGIN_TOP + plot_height / 2.0,
+        x_label = escape(x_label),
+        y_label = escape(y_label)
+    );
+
+    for (i, s) in series.iter().enumerate() {
+        let path: Vec<String> = s
+            .points
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", sx(*x), sy(*y)))
+            .collect();
+        let dash = if s.dashed {
+            " stroke-dasharray=\"6 4\""
+        } else {
+            ""
+        };
+        let ly = MARGIN_TOP + 8.0 + i as f64 * 18.0;
+        let lx = MARGIN_LEFT + plot_width + 12.0;
+        let _ = write!(
+            svg,
+            "<polyline points=\"{points}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\"{dash}/>\
+             <line x1=\"{lx}\" x2=\"{lx2}\" y1=\"{ly}\" y2=\"{ly}\" stroke=\"{color}\" stroke-width=\"2\"{dash}/>\
+             <text x=\"{tx}\" y=\"{ty}\">{name}</text>",
+            points = path.join(" "),
+            color = s.color,
+            dash = dash,
+            lx = lx,
+            lx2 = lx + 20.0,
+            ly = ly,
+            tx = lx + 26.0,
+            ty = ly + 4.0,
+            name = escape(&s.name)
+        );
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+fn usage_chart(result: &ProfileResult, total_memory: u64) -> String {
+    let memory_percent = |memory: u64| memory as f64 / total_memory.max(1) as f64 * 100.0;
+    let build: Vec<(f64, ProcessTreeSample)> = result
+        .datapoints
+        .iter()
+        .filter_map(|d| Some((d.elapsed, d.procs()?)))
+        .collect();
+    let mut series = vec![
+        Series::new(
+            "CPU usage",
+            "red",
+            result
+                .datapoints
+                .iter()
+                .filter_map(|d| Some((d.elapsed, d.cpu()?.usage as f64)))
+                .collect(),
+        ),
+        Series::new(
+            "Memory usage",
+            "blue",
+            result
+                .datapoints
+                .iter()
+                .filter_map(|d| Some((d.elapsed, memory_percent(d.mem()?.used))))
+                .collect(),
+        ),
+    ];
+    if build.iter().any(|(_, b)| b.memory_usage > 0) {
+        series.push(
+            Series::new(
+                "Build CPU usage",
+                "red",
+                build
+                    .iter()
+                    .map(|(elapsed, b)| (*elapsed, b.cpu_usage as f64))
+                    .collect(),
+            )
+            .dashed(),
+        );
+        series.push(
+            Series::new(
+                "Build memory usage",
+                "blue",
+                build
+                    .iter()
+                    .map(|(elapsed, b)| (*elapsed, memory_percent(b.memory_usage)))
+                    .collect(),
+            )
+            .dashed(),
+        );
+    }
+
+    line_chart(
+        &format!("CPU and Memory Usage, {} jobs", result.jobs),
+        "Time (s)",
+        "Usage (%)",
+        &series,
+    )
+}
+
+fn cores_chart(result: &ProfileResult) -> String {
+    let samples: Vec<(f64, Vec<f32>)> = result
+        .datapoints
+        .iter()
+        .filter_map(|d| Some((d.elapsed, d.cpu()?.cores)))
+        .collect();
+    let cores = samples
+        .iter()
+        .map(|(_, cores)| cores.len())
+        .max()
+        .unwrap_or(0);
+    let series: Vec<Series> = (0..cores)
+        .map(|core| {
+            Series::new(
+                &format!("CPU Core {}", core),
+                PALETTE[core % PALETTE.len()],
+                samples
+                    .iter()
+                    .filter_map(|(elapsed, cores)| Some((*elapsed, *cores.get(core)? as f64)))
+                    .collect(),
+            )
+        })
+        .collect();
+
+    line_chart(
+        &format!(
+            "CPU Cores Utilization ({} cores, {} jobs)",
+            cores, result.jobs
+        ),
+        "Time (s)",
+        "CPU Cores Utilization (%)",
+        &series,
+    )
+}
+
+fn time_charts(report: &Report) -> String {
+    let mut summaries = summary::summarize(&report.profile_results);
+    summaries.sort_by_key(|s| s.jobs);
+    let points = |f: &dyn Fn(&summary::JobsSummary) -> f64| -> Vec<(f64, f64)> {
+        summaries.iter().map(|s| (s.jobs as f64, f(s))).collect()
+    };
+
+    let time = line_chart(
+        "Time",
+        "Jobs",
+        "Time (s)",
+        &[
+            Series::new("Elapsed Time", "red", points(&|s| s.elapsed_time.mean)),
+            Series::new("User Time", "blue", points(&|s| s.user_time.mean)),
+            Series::new("System Time", "green", points(&|s| s.system_time.mean)),
+            Series::new(
+                "User + System Time",
+                "orange",
+                points(&|s| s.user_time.mean + s.system_time.mean),
+            ),
+        ],
+    );
+    let cpu_time = line_chart(
+        &format!(
+            "Effective CPU utilization ({} cores)",
+            report.system_info.num_cpus
+        ),
+        "Jobs",
+        "Cores used",
+        &[
+            Series::new(
+                "Reference CPU utilization",
+                "red",
+                points(&|s| s.jobs as f64),
+            )
+            .dashed(),
+            Series::new(
+                "Effective CPU utilization",
+                "blue",
+                points(&|s| (s.user_time.mean + s.system_time.mean) / s.elapsed_time.mean),
+            ),
+        ],
+    );
+
+    time + &cpu_time
+}
+
+fn table(rows: &[(&str, String)]) -> String {
+    let mut html = String::from("<table>");
+    for (name, value) in rows {
+        let _ = write!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(name),
+            escape(value)
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+pub fn to_html(report: &Report) -> String {
+    let info = &report.system_info;
+    let settings = &report.profile_settings;
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>bsprof report</title><style>\
+         body{font-family:sans-serif;margin:2em;color:#222}\
+         table{border-collapse:collapse;margin-bottom:1em}\
+         th,td{border:1px solid #ccc;padding:4px 10px;text-align:left}\
+         svg{display:block;margin:1em 0}\
+         svg text{font-size:12px;fill:#222}svg text.title{font-size:15px;font-weight:bold}\
+         .grid{stroke:#e0e0e0}.frame{fill:none;stroke:#888}\
+         </style></head><body>\n<h1>bsprof report</h1>\n",
+    );
+
+    html.push_str("<h2>System</h2>\n");
+    html.push_str(&table(&[
+        ("OS", info.os.clone()),
+        ("CPU", info.cpu_name.clone()),
+        ("CPUs", info.num_cpus.to_string()),
+        (
+            "Total memory",
+            format!("{} MB", info.total_memory / 1024 / 1024),
+        ),
+    ]));
+    html.push_str("\n<h2>Settings</h2>\n");
+    html.push_str(&table(&[
+        ("Interval", format!("{} ms", settings.interval.as_millis())),
+        ("Warmup", format!("{} ms", settings.warmup.as_millis())),
+        ("Cooldown", format!("{} ms", settings.cooldown.as_millis())),
+    ]));
+
+    if !report.profile_results.is_empty() {
+        html.push_str("\n<h2>Time vs jobs</h2>\n");
+        html.push_str(&time_charts(report));
+    }
+
+    for result in &report.profile_results {
+        let _ = write!(
+            html,
+            "\n<h2>{} jobs, run {}</h2>\n<p><code>{} {}</code>, elapsed {:.3} s</p>\n",
+            result.jobs,
+            result.run + 1,
+            escape(&result.cmd_name),
+            escape(&result.cmd_args.join(" ")),
+            result.elapsed_time
+        );
+        html.push_str(&usage_chart(result, info.total_memory));
+        html.push_str(&cores_chart(result));
+    }
+
+    html.push_str("\n</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks() {
+        assert_eq!(ticks(0.0, 100.0), vec![0.0, 20.0, 40.0, 60.0, 80.0, 100.0]);
+        assert_eq!(ticks(0.0, 7.0), vec![0.0, 2.0, 4.0, 6.0]);
+        assert_eq!(ticks(3.0, 3.0), vec![3.0]);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("a < b && \"c\""),
+            "a &lt; b &amp;&amp; &quot;c&quot;"
+        );
+    }
+}
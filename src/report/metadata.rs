@@ -0,0 +1,173 @@
+//! Provenance of a report: who ran bsprof, where, how and on which revision
+//! of the built project.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GitState {
+    pub commit: String,
+    /// Tracked files have uncommitted changes.
+    pub dirty: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Metadata {
+    pub version: String,
+    /// Wall-clock times in UTC, RFC 3339.
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub hostname: String,
+    pub argv: Vec<String>,
+    pub cwd: String,
+    /// Revision of the project in the working directory, `None` outside of
+    /// a git repository.
+    pub git: Option<GitState>,
+    #[serde(skip)]
+    started: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn collect() -> Metadata {
+        let now = SystemTime::now();
+        Metadata {
+            version: clap::crate_version!().to_string(),
+            start_time: format_time(now, "%Y-%m-%dT%H:%M:%SZ", true),
+            end_time: None,
+            hostname: hostname(),
+            argv: std::env::args().collect(),
+            cwd: std::env::current_dir()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            git: git_state(),
+            started: Some(now),
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.end_time = Some(format_time(SystemTime::now(), "%Y-%m-%dT%H:%M:%SZ", true));
+    }
+
+    /// Expands `{date}` (local start time), `{host}`, `{git_rev}` and
+    /// `{jobs}` in an output path template.
+    pub fn expand(&self, template: &str, jobs: &str) -> Result<String> {
+        let mut path = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            path.push_str(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(|| {
+                Error::InvalidArgument(format!("unclosed {{ in \"{}\"", template))
+            })?;
+            let value = match &rest[start + 1..start + end] {
+                "date" => format_time(
+                    self.started.unwrap_or_else(SystemTime::now),
+                    "%Y%m%d-%H%M%S",
+                    false,
+                ),
+                "host" => self.hostname.clone(),
+                "git_rev" => match &self.git {
+                    Some(git) if git.dirty => format!("{}-dirty", git.commit),
+                    Some(git) => git.commit.clone(),
+                    None => "nogit".to_string(),
+                },
+                "jobs" => jobs.to_string(),
+                name => {
+                    return Err(Error::InvalidArgument(format!(
+                        "unknown placeholder {{{}}} in \"{}\"",
+                        name, template
+                    )))
+                }
+            };
+            path.push_str(&value);
+            rest = &rest[start + end + 1..];
+        }
+        path.push_str(rest);
+        Ok(path)
+    }
+}
+
+fn format_time(time: SystemTime, format: &str, utc: bool) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()) as libc::time_t;
+    let format = std::ffi::CString::new(format).unwrap_or_default();
+    let mut buffer = [0u8; 64];
+    let length = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if utc {
+            libc::gmtime_r(&seconds, &mut tm);
+        } else {
+            libc::localtime_r(&seconds, &mut tm);
+        }
+        libc::strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return "unknown".to_string();
+    }
+    let length = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn git_state() -> Option<GitState> {
+    Some(GitState {
+        commit: git(&["rev-parse", "--short", "HEAD"])?,
+        dirty: !git(&["status", "--porcelain", "--untracked-files=no"])?.is_empty(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let metadata = Metadata {
+            version: "0.1.0".to_string(),
+            start_time: String::new(),
+            end_time: None,
+            hostname: "builder".to_string(),
+            argv: Vec::new(),
+            cwd: String::new(),
+            git: Some(GitState {
+                commit: "abc1234".to_string(),
+                dirty: true,
+            }),
+            started: Some(SystemTime::UNIX_EPOCH),
+        };
+
+        assert_eq!(
+            metadata
+                .expand("out/{host}-{git_rev}-j{jobs}.json", "1-8")
+                .unwrap(),
+            "out/builder-abc1234-dirty-j1-8.json"
+        );
+        assert_eq!(metadata.expand("{date}", "1").unwrap().len(), 15);
+        assert!(metadata.expand("{user}.json", "1").is_err());
+        assert!(metadata.expand("{date.json", "1").is_err());
+    }
+}
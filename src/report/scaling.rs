@@ -0,0 +1,168 @@
+use super::summary::JobsSummary;
+use serde::{Deserialize, Serialize};
+
+// job counts within this fraction of the best elapsed time are considered
+// equally fast
+pub const KNEE_TOLERANCE: f64 = 0.05;
+// job counts using more than this fraction of total memory are avoided
+const MEMORY_LIMIT: f64 = 0.9;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JobsScaling {
+    pub jobs: u32,
+    pub speedup: f64,
+    pub efficiency: f64,
+    pub amdahl_time: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Scaling {
+    pub baseline_jobs: u32,
+    pub jobs: Vec<JobsScaling>,
+    pub serial_fraction: f64,
+    /// `None` when the fitted serial time is zero and the speedup has no
+    /// bound.
+    pub max_speedup: Option<f64>,
+    pub fit_r_squared: f64,
+    pub recommended_jobs: u32,
+    pub recommendation: String,
+}
+
+/// Fits `T(n) = a + b / n` with least squares, returns `(a, b, r^2)`.
+fn fit_amdahl(points: &[(f64, f64)]) -> Option<(f64, f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let syy: f64 = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let b = sxy / sxx;
+    let a = mean_y - b * mean_x;
+    let r_squared = if syy > 0.0 {
+        sxy * sxy / (sxx * syy)
+    } else {
+        1.0
+    };
+    Some((a, b, r_squared))
+}
+
+pub fn analyze(summaries: &[JobsSummary], total_memory: u64) -> Option<Scaling> {
+    let mut summaries: Vec<&JobsSummary> = summaries.iter().collect();
+    summaries.sort_by_key(|s| s.jobs);
+    let baseline = *summaries.first()?;
+
+    let points: Vec<(f64, f64)> = summaries
+        .iter()
+        .map(|s| (1.0 / s.jobs as f64, s.elapsed_time.mean))
+        .collect();
+    let (a, b, fit_r_squared) = fit_amdahl(&points)?;
+    let (a, b) = (a.max(0.0), b.max(0.0));
+    let serial_fraction = if a + b > 0.0 { a / (a + b) } else { 0.0 };
+
+    let jobs = summaries
+        .iter()
+        .map(|s| {
+            let speedup = baseline.elapsed_time.mean / s.elapsed_time.mean;
+            JobsScaling {
+                jobs: s.jobs,
+                speedup,
+                efficiency: speedup * baseline.jobs as f64 / s.jobs as f64,
+                amdahl_time: a + b / s.jobs as f64,
+            }
+        })
+        .collect();
+
+    let memory_limit = (total_memory as f64 * MEMORY_LIMIT) as u64;
+    let candidates: Vec<&&JobsSummary> = summaries
+        .iter()
+        .filter(|s| s.peak_memory < memory_limit)
+        .collect();
+    let (recommended_jobs, recommendation) = match candidates
+        .iter()
+        .map(|s| s.elapsed_time.mean)
+        .min_by(|a, b| a.total_cmp(b))
+    {
+        Some(best) => {
+            let knee = candidates
+                .iter()
+                .find(|s| s.elapsed_time.mean <= best * (1.0 + KNEE_TOLERANCE))
+                .unwrap();
+            let reason = if candidates.len() < summaries.len() {
+                "lowest number of jobs within 5% of the best time, with memory usage below 90% of total memory"
+            } else {
+                "lowest number of jobs within 5% of the best time"
+            };
+            (knee.jobs, reason.to_string())
+        }
+        None => (
+            baseline.jobs,
+            "all job counts use more than 90% of total memory".to_string(),
+        ),
+    };
+
+    Some(Scaling {
+        baseline_jobs: baseline.jobs,
+        jobs,
+        serial_fraction,
+        max_speedup: if a > 0.0 { Some((a + b) / a) } else { None },
+        fit_r_squared,
+        recommended_jobs,
+        recommendation,
+    })
+}
+
+pub fn print(scaling: &Scaling) {
+    println!(
+        "{:>6} {:>10} {:>11} {:>12}",
+        "jobs", "speedup", "efficiency", "amdahl [s]"
+    );
+    for j in &scaling.jobs {
+        println!(
+            "{:>6} {:>10.2} {:>10.1}% {:>12.3}",
+            j.jobs,
+            j.speedup,
+            j.efficiency * 100.0,
+            j.amdahl_time
+        );
+    }
+    println!(
+        "Serial fraction: {:.1}% (max speedup {}, fit R^2 {:.3})",
+        scaling.serial_fraction * 100.0,
+        scaling
+            .max_speedup
+            .map_or("unbounded".to_string(), |speedup| format!("{:.2}", speedup)),
+        scaling.fit_r_squared
+    );
+    println!(
+        "Recommended jobs: {} ({})",
+        scaling.recommended_jobs, scaling.recommendation
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_amdahl() {
+        // 20% serial fraction of a 100 s build
+        let points: Vec<(f64, f64)> = [1.0, 2.0, 4.0, 8.0]
+            .iter()
+            .map(|n| (1.0 / n, 20.0 + 80.0 / n))
+            .collect();
+
+        let (a, b, r_squared) = fit_amdahl(&points).unwrap();
+
+        assert!((a - 20.0).abs() < 1e-9);
+        assert!((b - 80.0).abs() < 1e-9);
+        assert!((r_squared - 1.0).abs() < 1e-9);
+        assert!(fit_amdahl(&[(1.0, 100.0)]).is_none());
+    }
+}
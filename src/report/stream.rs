@@ -0,0 +1,211 @@
+//! Newline-delimited JSON report written while profiling, so a crash loses
+//! at most the last line. Every line is one record: a header, the data
+//! points and the results of the runs as they complete, and an end record.
+
+use super::{metadata::Metadata, Report};
+use crate::error::{Error, Result};
+use crate::profile::{info::SystemInfo, ProfileDatapoint, ProfileResult, ProfileSettings};
+use crate::sequence::SequenceInfo;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Header {
+        system_info: SystemInfo,
+        profile_settings: Box<ProfileSettings>,
+        metadata: Option<Metadata>,
+    },
+    Datapoint {
+        run_id: usize,
+        datapoint: ProfileDatapoint,
+    },
+    Result {
+        run_id: usize,
+        result: Box<ProfileResult>,
+    },
+    End {
+        sequence: Option<SequenceInfo>,
+        failure: Option<String>,
+        metadata: Option<Metadata>,
+    },
+}
+
+/// Appends records to the stream, clones share the same file.
+#[derive(Clone)]
+pub struct StreamWriter {
+    path: String,
+    file: Arc<Mutex<std::fs::File>>,
+}
+
+impl StreamWriter {
+    /// Creates the stream and writes the header from the new `report`.
+    pub fn create(path: &str, report: &Report) -> Result<StreamWriter> {
+        let file = std::fs::File::create(path).map_err(|e| Error::io(path, e))?;
+        let writer = StreamWriter {
+            path: path.to_string(),
+            file: Arc::new(Mutex::new(file)),
+        };
+        writer.write(&json!({
+            "type": "header",
+            "system_info": report.system_info,
+            "profile_settings": report.profile_settings,
+            "metadata": report.metadata,
+        }))?;
+        Ok(writer)
+    }
+
+    /// Writes a record as a single line with a single write call.
+    fn write(&self, record: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(&line).map_err(|e| Error::io(&self.path, e))
+    }
+
+    pub fn datapoint(&self, run_id: usize, datapoint: &ProfileDatapoint) -> Result<()> {
+        self.write(&json!({
+            "type": "datapoint",
+            "run_id": run_id,
+            "datapoint": datapoint,
+        }))
+    }
+
+    /// Writes a completed run, its data points were already streamed.
+    pub fn result(&self, run_id: usize, result: &ProfileResult) -> Result<()> {
+        let mut result = serde_json::to_value(result)?;
+        result["datapoints"] = json!([]);
+        self.write(&json!({
+            "type": "result",
+            "run_id": run_id,
+            "result": result,
+        }))
+    }
+
+    pub fn end(&self, report: &Report) -> Result<()> {
+        self.write(&json!({
+            "type": "end",
+            "sequence": report.sequence,
+            "memory": report.memory,
+            "failure": report.failure,
+            "metadata": report.metadata,
+        }))
+    }
+}
+
+/// Tells a stream from a pretty report by its first line.
+pub fn is_stream(content: &str) -> bool {
+    content
+        .lines()
+        .next()
+        .and_then(|line| serde_json::from_str::<Value>(line).ok())
+        .is_some_and(|record| record["type"] == "header")
+}
+
+/// Rebuilds the report from a stream. A truncated last line is skipped,
+/// and a stream without its end record is loaded with the completed runs.
+pub fn load(content: &str) -> Result<Report> {
+    let lines: Vec<&str> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let mut report: Option<Report> = None;
+    let mut datapoints: BTreeMap<usize, Vec<ProfileDatapoint>> = BTreeMap::new();
+    let mut results: Vec<(usize, ProfileResult)> = Vec::new();
+    let mut ended = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        let record = match serde_json::from_str::<Record>(line) {
+            Ok(record) => record,
+            Err(_) if i + 1 == lines.len() => {
+                eprintln!("Warning: skipping the truncated last line of the stream");
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        match (record, report.as_mut()) {
+            (
+                Record::Header {
+                    system_info,
+                    profile_settings,
+                    metadata,
+                },
+                None,
+            ) => {
+                let mut header = Report::new(&system_info, &profile_settings);
+                header.metadata = metadata;
+                report = Some(header);
+            }
+            (Record::Datapoint { run_id, datapoint }, Some(_)) => {
+                datapoints.entry(run_id).or_default().push(datapoint);
+            }
+            (Record::Result { run_id, result }, Some(_)) => results.push((run_id, *result)),
+            (
+                Record::End {
+                    sequence,
+                    failure,
+                    metadata,
+                },
+                Some(report),
+            ) => {
+                report.sequence = sequence;
+                report.failure = failure;
+                if metadata.is_some() {
+                    report.metadata = metadata;
+                }
+                ended = true;
+            }
+            _ => return Err(Error::invalid_argument("stream records out of order")),
+        }
+    }
+
+    let mut report = report.ok_or_else(|| Error::invalid_argument("stream has no header"))?;
+    for (run_id, mut result) in results {
+        result.datapoints = datapoints.remove(&run_id).unwrap_or_default();
+        report.add_result(result);
+    }
+    if !ended && report.failure.is_none() {
+        let mut failure = "stream ended before profiling finished".to_string();
+        if let Some((_, incomplete)) = datapoints.iter().next_back() {
+            failure += &format!(", {} data points of an unfinished run", incomplete.len());
+        }
+        report.failure = Some(failure);
+    }
+    report.summarize();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_truncated_stream() {
+        let info = crate::profile::info::get_system_info();
+        let settings =
+            ProfileSettings::new(Duration::from_millis(100), Duration::ZERO, Duration::ZERO);
+        let path =
+            std::env::temp_dir().join(format!("bsprof-stream-{}.ndjson", std::process::id()));
+        let path = path.to_str().unwrap();
+        let stream = StreamWriter::create(path, &Report::new(&info, &settings)).unwrap();
+        stream.datapoint(0, &ProfileDatapoint::default()).unwrap();
+        stream.datapoint(0, &ProfileDatapoint::default()).unwrap();
+
+        let mut content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        content.push_str("{\"type\": \"result\", \"run_id\": 0, \"res");
+        assert!(is_stream(&content));
+
+        let report = load(&content).unwrap();
+        assert!(report.profile_results.is_empty());
+        assert_eq!(
+            report.failure.as_deref(),
+            Some("stream ended before profiling finished, 2 data points of an unfinished run")
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::profile::{
+    memory::MemorySample, procs::ProcessTreeSample, psi::PressureSample, ProfileResult,
+};
+use crate::stats::Statistics;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JobsSummary {
+    pub jobs: u32,
+    pub runs: u32,
+    pub elapsed_time: Statistics,
+    pub user_time: Statistics,
+    pub system_time: Statistics,
+    #[serde(default)]
+    pub peak_memory: u64,
+    /// Mean time per run in seconds some tasks were stalled on each
+    /// resource, `None` when pressure stall information is not available.
+    #[serde(default)]
+    pub stall: Option<StallTime>,
+    #[serde(default)]
+    pub memory: Option<MemorySummary>,
+}
+
+/// Peak memory values over all runs with the given number of jobs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemorySummary {
+    pub peak_build_rss: u64,
+    pub peak_build_pss: u64,
+    pub peak_swap_used: u64,
+    pub peak_cache: u64,
+    pub peak_dirty: u64,
+    pub min_available: u64,
+    pub swap_out: u64,
+    pub oom_kills: u64,
+    /// Pages were swapped out while the build was running.
+    pub swapping: bool,
+}
+
+fn memory_summary(runs: &[&ProfileResult]) -> Option<MemorySummary> {
+    let samples: Vec<MemorySample> = runs
+        .iter()
+        .flat_map(|r| r.datapoints.iter().filter_map(|d| d.mem()))
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+
+    let peak = |f: fn(&MemorySample) -> u64| samples.iter().map(f).max().unwrap_or(0);
+    let build: Vec<ProcessTreeSample> = runs
+        .iter()
+        .flat_map(|r| r.datapoints.iter().filter_map(|d| d.procs()))
+        .collect();
+    let peak_build = |f: fn(&ProcessTreeSample) -> u64| build.iter().map(f).max().unwrap_or(0);
+    // swap and OOM counters are cumulative within a run
+    let total = |f: fn(&MemorySample) -> u64| {
+        runs.iter()
+            .filter_map(|r| r.datapoints.iter().rev().find_map(|d| d.mem()))
+            .map(|s| f(&s))
+            .sum::<u64>()
+    };
+    let swap_out = total(|s| s.swap_out);
+
+    Some(MemorySummary {
+        peak_build_rss: peak_build(|s| s.memory_usage),
+        peak_build_pss: peak_build(|s| s.pss_usage),
+        peak_swap_used: peak(|s| s.swap_used),
+        peak_cache: peak(|s| s.cache),
+        peak_dirty: peak(|s| s.dirty),
+        min_available: samples.iter().map(|s| s.available).min().unwrap_or(0),
+        swap_out,
+        oom_kills: total(|s| s.oom_kills),
+        swapping: swap_out > 0,
+    })
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct StallTime {
+    pub cpu: f64,
+    pub memory: f64,
+    pub io: f64,
+}
+
+fn stall_time(result: &ProfileResult) -> Option<StallTime> {
+    let mut stall = None;
+    for psi in result.datapoints.iter().filter_map(|d| d.psi()) {
+        let total: &mut StallTime = stall.get_or_insert_with(StallTime::default);
+        let seconds =
+            |pressure: Option<PressureSample>| pressure.map_or(0.0, |p| p.some_stall as f64 / 1e6);
+        total.cpu += seconds(psi.cpu);
+        total.memory += seconds(psi.memory);
+        total.io += seconds(psi.io);
+    }
+    stall
+}
+
+/// Job counts in the order in which they were first profiled.
+fn job_counts(results: &[ProfileResult]) -> Vec<u32> {
+    let mut jobs: Vec<u32> = Vec::new();
+    for result in results {
+        if !jobs.contains(&result.jobs) {
+            jobs.push(result.jobs);
+        }
+    }
+    jobs
+}
+
+/// Memory summary of a single run, `None` without memory samples.
+pub fn run_memory(result: &ProfileResult) -> Option<MemorySummary> {
+    memory_summary(&[result])
+}
+
+/// Memory summary of every profiled job count. Failed runs are included,
+/// swapping and OOM kills are often what made the build fail.
+pub fn memory_by_jobs(results: &[ProfileResult]) -> Vec<(u32, MemorySummary)> {
+    job_counts(results)
+        .into_iter()
+        .filter_map(|j| {
+            let runs: Vec<&ProfileResult> = results.iter().filter(|r| r.jobs == j).collect();
+            Some((j, memory_summary(&runs)?))
+        })
+        .collect()
+}
+
+/// Groups successful results by job count, keeping the order in which job
+/// counts were first profiled. The memory summary covers failed runs too.
+pub fn summarize(results: &[ProfileResult]) -> Vec<JobsSummary> {
+    let memory = memory_by_jobs(results);
+    let successful: Vec<&ProfileResult> =
+        results.iter().filter(|r| r.status.is_success()).collect();
+
+    job_counts(results)
+        .into_iter()
+        .filter_map(|j| {
+            let runs: Vec<&ProfileResult> =
+                successful.iter().copied().filter(|r| r.jobs == j).collect();
+            let elapsed: Vec<f64> = runs.iter().map(|r| r.elapsed_time).collect();
+            let user: Vec<f64> = runs
+                .iter()
+                .map(|r| r.rusage.user_time.as_secs_f64())
+                .collect();
+            let system: Vec<f64> = runs
+                .iter()
+                .map(|r| r.rusage.system_time.as_secs_f64())
+                .collect();
+
+            let peak_memory = runs
+                .iter()
+                .flat_map(|r| r.datapoints.iter().filter_map(|d| d.mem()))
+                .map(|m| m.used)
+                .max()
+                .unwrap_or(0);
+
+            let stalls: Vec<StallTime> = runs.iter().filter_map(|r| stall_time(r)).collect();
+            let stall = if stalls.is_empty() {
+                None
+            } else {
+                let n = stalls.len() as f64;
+                Some(StallTime {
+                    cpu: stalls.iter().map(|s| s.cpu).sum::<f64>() / n,
+                    memory: stalls.iter().map(|s| s.memory).sum::<f64>() / n,
+                    io: stalls.iter().map(|s| s.io).sum::<f64>() / n,
+                })
+            };
+
+            Some(JobsSummary {
+                jobs: j,
+                runs: runs.len() as u32,
+                elapsed_time: Statistics::from_samples(&elapsed)?,
+                user_time: Statistics::from_samples(&user)?,
+                system_time: Statistics::from_samples(&system)?,
+                peak_memory,
+                stall,
+                memory: memory
+                    .iter()
+                    .find(|(jobs, _)| *jobs == j)
+                    .map(|(_, memory)| *memory),
+            })
+        })
+        .collect()
+}
+
+pub fn print(
+    summaries: &[JobsSummary],
+    memory: &[(u32, MemorySummary)],
+    results: &[ProfileResult],
+) {
+    print_times(summaries);
+    print_stalls(summaries);
+    print_memory(memory, results);
+}
+
+fn print_times(summaries: &[JobsSummary]) {
+    if summaries.is_empty() {
+        return;
+    }
+
+    println!(
+        "{:>6} {:>5} {:>10} {:>10} {:>9} {:>10} {:>10} {:>21} {:>10} {:>10}",
+        "jobs",
+        "runs",
+        "mean [s]",
+        "median",
+        "stddev",
+        "min",
+        "max",
+        "95% CI",
+        "user [s]",
+        "sys [s]"
+    );
+    for s in summaries {
+        println!(
+            "{:>6} {:>5} {:>10.3} {:>10.3} {:>9.3} {:>10.3} {:>10.3} {:>21} {:>10.3} {:>10.3}",
+            s.jobs,
+            s.runs,
+            s.elapsed_time.mean,
+            s.elapsed_time.median,
+            s.elapsed_time.stddev,
+            s.elapsed_time.min,
+            s.elapsed_time.max,
+            format!(
+                "[{:.3}, {:.3}]",
+                s.elapsed_time.ci95_low, s.elapsed_time.ci95_high
+            ),
+            s.user_time.mean,
+            s.system_time.mean
+        );
+    }
+}
+
+/// Prints the stall time per resource, the point where memory or I/O stalls
+/// start growing with the number of jobs marks contention.
+fn print_stalls(summaries: &[JobsSummary]) {
+    if summaries.iter().all(|s| s.stall.is_none()) {
+        return;
+    }
+
+    println!(
+        "{:>6} {:>14} {:>14} {:>14}",
+        "jobs", "cpu stall [s]", "mem stall [s]", "io stall [s]"
+    );
+    for s in summaries {
+        match &s.stall {
+            Some(stall) => println!(
+                "{:>6} {:>14.3} {:>14.3} {:>14.3}",
+                s.jobs, stall.cpu, stall.memory, stall.io
+            ),
+            None => println!("{:>6} {:>14} {:>14} {:>14}", s.jobs, "-", "-", "-"),
+        }
+    }
+}
+
+/// Prints the memory of every job count, including the ones where all the
+/// runs failed.
+fn print_memory(memory: &[(u32, MemorySummary)], results: &[ProfileResult]) {
+    if memory.is_empty() {
+        return;
+    }
+
+    let mb = |bytes: u64| bytes / 1024 / 1024;
+    println!(
+        "{:>6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  flags",
+        "jobs", "rss [MB]", "pss [MB]", "avail", "swap", "cache", "dirty"
+    );
+    for &(jobs, memory) in memory {
+        let mut flags = Vec::new();
+        if memory.swapping {
+            flags.push("SWAPPING".to_string());
+        }
+        if memory.oom_kills > 0 {
+            flags.push(format!("OOM ({} kills)", memory.oom_kills));
+        }
+        let failed = results
+            .iter()
+            .filter(|r| r.jobs == jobs && !r.status.is_success())
+            .count();
+        if failed > 0 {
+            flags.push(format!("{} failed", failed));
+        }
+        let line = format!(
+            "{:>6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  {}",
+            jobs,
+            mb(memory.peak_build_rss),
+            mb(memory.peak_build_pss),
+            mb(memory.min_available),
+            mb(memory.peak_swap_used),
+            mb(memory.peak_cache),
+            mb(memory.peak_dirty),
+            flags.join(" ")
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{sampler, ProfileDatapoint, RunStatus};
+
+    fn result(jobs: u32, status: RunStatus, oom_kills: u64) -> ProfileResult {
+        let mut datapoint = ProfileDatapoint::default();
+        let memory = MemorySample {
+            oom_kills,
+            ..Default::default()
+        };
+        datapoint.samples.insert(
+            sampler::MEM.to_string(),
+            serde_json::to_value(memory).unwrap(),
+        );
+        ProfileResult {
+            elapsed_time: 10.0,
+            jobs,
+            status,
+            datapoints: vec![datapoint],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_oom_in_failed_run() {
+        let killed = RunStatus::Signaled { signal: 9 };
+        let results = vec![
+            result(4, RunStatus::Success, 0),
+            result(4, killed, 2),
+            result(8, killed, 3),
+        ];
+
+        let summaries = summarize(&results);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].runs, 1);
+        assert_eq!(summaries[0].memory.unwrap().oom_kills, 2);
+
+        let memory = memory_by_jobs(&results);
+        assert_eq!(memory.len(), 2);
+        assert_eq!((memory[1].0, memory[1].1.oom_kills), (8, 3));
+    }
+}
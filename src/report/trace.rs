@@ -0,0 +1,178 @@
+//! Chrome Trace Event Format export, loadable in chrome://tracing and
+//! ui.perfetto.dev.
+
+use super::Report;
+use crate::profile::{procs::ProcessLifetime, ProfileResult};
+use serde_json::{json, Value};
+
+const US_PER_SEC: f64 = 1_000_000.0;
+
+pub fn to_trace(report: &Report) -> Value {
+    let mut events = Vec::new();
+
+    for (i, result) in report.profile_results.iter().enumerate() {
+        let pid = i + 1;
+        events.push(json!({
+            "name": "process_name",
+            "ph": "M",
+            "pid": pid,
+            "args": { "name": format!("{} jobs", result.jobs) },
+        }));
+        events.push(json!({
+            "name": "process_sort_index",
+            "ph": "M",
+            "pid": pid,
+            "args": { "sort_index": pid },
+        }));
+        add_process_slices(&mut events, pid, result);
+        add_counters(&mut events, pid, result, report.system_info.total_memory);
+    }
+
+    json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+    })
+}
+
+fn add_process_slices(events: &mut Vec<Value>, pid: usize, result: &ProfileResult) {
+    let lanes = assign_lanes(&result.processes);
+
+    for lane in 0..lanes.iter().max().map_or(0, |max| max + 1) {
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": pid,
+            "tid": lane,
+            "args": { "name": format!("lane {}", lane) },
+        }));
+    }
+
+    for (process, lane) in result.processes.iter().zip(lanes) {
+        events.push(json!({
+            "name": short_name(&process.cmdline),
+            "cat": "process",
+            "ph": "X",
+            "pid": pid,
+            "tid": lane,
+            "ts": process.start_time * US_PER_SEC,
+            "dur": (process.end_time - process.start_time).max(0.0) * US_PER_SEC,
+            "args": {
+                "pid": process.pid,
+                "ppid": process.ppid,
+                "cmdline": process.cmdline,
+                "cpu_time": process.cpu_time,
+                "max_rss": process.max_rss,
+            },
+        }));
+    }
+}
+
+fn add_counters(events: &mut Vec<Value>, pid: usize, result: &ProfileResult, total_memory: u64) {
+    for datapoint in &result.datapoints {
+        let ts = datapoint.elapsed * US_PER_SEC;
+        let cpu = datapoint.cpu().unwrap_or_default();
+        let mem = datapoint.mem().unwrap_or_default();
+        let build = datapoint.procs().unwrap_or_default();
+        events.push(json!({
+            "name": "CPU usage (%)",
+            "ph": "C",
+            "pid": pid,
+            "ts": ts,
+            "args": {
+                "system": cpu.usage,
+                "build": build.cpu_usage,
+            },
+        }));
+        events.push(json!({
+            "name": "Memory usage (MB)",
+            "ph": "C",
+            "pid": pid,
+            "ts": ts,
+            "args": {
+                "system": mem.used / 1024 / 1024,
+                "build": build.memory_usage / 1024 / 1024,
+                "total": total_memory / 1024 / 1024,
+            },
+        }));
+        for (core, usage) in cpu.cores.iter().enumerate() {
+            events.push(json!({
+                "name": format!("CPU {} (%)", core),
+                "ph": "C",
+                "pid": pid,
+                "ts": ts,
+                "args": { "usage": usage },
+            }));
+        }
+    }
+}
+
+/// Reconstructs concurrency by greedily placing every process on the lowest
+/// lane that is free at its start time.
+fn assign_lanes(processes: &[ProcessLifetime]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..processes.len()).collect();
+    order.sort_by(|a, b| {
+        processes[*a]
+            .start_time
+            .total_cmp(&processes[*b].start_time)
+    });
+
+    let mut lane_ends: Vec<f64> = Vec::new();
+    let mut lanes = vec![0; processes.len()];
+
+    for i in order {
+        let process = &processes[i];
+        let lane = match lane_ends.iter().position(|end| *end <= process.start_time) {
+            Some(lane) => lane,
+            None => {
+                lane_ends.push(0.0);
+                lane_ends.len() - 1
+            }
+        };
+        lane_ends[lane] = process.end_time;
+        lanes[i] = lane;
+    }
+
+    lanes
+}
+
+fn short_name(cmdline: &str) -> String {
+    let program = cmdline.split_whitespace().next().unwrap_or("?");
+    program.rsplit('/').next().unwrap_or(program).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(start_time: f64, end_time: f64) -> ProcessLifetime {
+        ProcessLifetime {
+            pid: 0,
+            ppid: 0,
+            cmdline: String::new(),
+            start_time,
+            end_time,
+            cpu_time: 0.0,
+            max_rss: 0,
+        }
+    }
+
+    #[test]
+    fn test_assign_lanes() {
+        let processes = vec![
+            process(0.0, 10.0),
+            process(1.0, 3.0),
+            process(2.0, 4.0),
+            process(3.0, 5.0),
+            process(6.0, 7.0),
+        ];
+
+        assert_eq!(assign_lanes(&processes), vec![0, 1, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name("/usr/bin/cc -c main.c"), "cc");
+        assert_eq!(short_name("ld"), "ld");
+        assert_eq!(short_name(""), "?");
+    }
+}
@@ -0,0 +1,286 @@
+//! Strategies choosing the numbers of jobs to profile.
+
+use super::error::{Error, Result};
+use super::report::{scaling::KNEE_TOLERANCE, summary::JobsSummary};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Strategy {
+    Fixed(Vec<u32>),
+    Adaptive { max: u32, tolerance: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SequenceInfo {
+    pub strategy: String,
+    pub visited: Vec<u32>,
+}
+
+impl Strategy {
+    /// Parses a jobs specification: a number, a comma separated list of
+    /// numbers and `start..end[:step=N]` inclusive ranges, `pow2` or
+    /// `adaptive[:tol=N]`. A single number is expanded to `1..=N` when
+    /// `sequence` is set.
+    pub fn parse(spec: Option<&str>, sequence: bool, max: u32) -> Result<Strategy> {
+        let spec = match spec {
+            Some(spec) => spec.trim(),
+            None => {
+                return Ok(Strategy::Fixed(if sequence {
+                    (1..=max).collect()
+                } else {
+                    vec![max]
+                }))
+            }
+        };
+
+        if spec == "pow2" {
+            return Ok(Strategy::Fixed(powers_of_two(max)));
+        }
+        if let Some(options) = spec.strip_prefix("adaptive") {
+            let tolerance = match options.strip_prefix(":tol=") {
+                Some(tolerance) => parse_jobs(tolerance)?,
+                None if options.is_empty() => 1,
+                None => return Err(Error::invalid_argument("invalid adaptive jobs options")),
+            };
+            return Ok(Strategy::Adaptive { max, tolerance });
+        }
+
+        let mut jobs = Vec::new();
+        for item in spec.split(',') {
+            match item.split_once("..") {
+                Some((start, rest)) => {
+                    let (end, step) = match rest.split_once(":step=") {
+                        Some((end, step)) => (end, parse_jobs(step)?),
+                        None => (rest, 1),
+                    };
+                    let (start, end) = (parse_jobs(start)?, parse_jobs(end)?);
+                    if start > end {
+                        return Err(Error::invalid_argument("invalid jobs range"));
+                    }
+                    jobs.extend((start..=end).step_by(step as usize));
+                }
+                None => jobs.push(parse_jobs(item)?),
+            }
+        }
+
+        if jobs.len() == 1 && sequence {
+            jobs = (1..=jobs[0]).collect();
+        }
+        Ok(Strategy::Fixed(jobs))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Strategy::Fixed(jobs) => format!("fixed {:?}", jobs),
+            Strategy::Adaptive { max, tolerance } => {
+                format!("adaptive (max {}, tolerance {})", max, tolerance)
+            }
+        }
+    }
+
+    /// Short form for file names: the number of jobs, the range of the
+    /// numbers of jobs or `adaptive`.
+    pub fn label(&self) -> String {
+        match self {
+            Strategy::Fixed(jobs) => {
+                let min = jobs.iter().min().copied().unwrap_or(0);
+                let max = jobs.iter().max().copied().unwrap_or(0);
+                if min == max {
+                    min.to_string()
+                } else {
+                    format!("{}-{}", min, max)
+                }
+            }
+            Strategy::Adaptive { .. } => "adaptive".to_string(),
+        }
+    }
+
+    pub fn sequencer(&self) -> Sequencer {
+        let queue = match self {
+            Strategy::Fixed(jobs) => jobs.clone(),
+            Strategy::Adaptive { max, .. } => powers_of_two(*max),
+        };
+        Sequencer {
+            strategy: self.clone(),
+            queue,
+            visited: Vec::new(),
+        }
+    }
+}
+
+fn parse_jobs(value: &str) -> Result<u32> {
+    match value.trim().parse::<u32>() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(Error::InvalidArgument(format!(
+            "invalid jobs value \"{}\"",
+            value
+        ))),
+    }
+}
+
+fn powers_of_two(max: u32) -> Vec<u32> {
+    let mut jobs: Vec<u32> = std::iter::successors(Some(1u32), |j| j.checked_mul(2))
+        .take_while(|j| *j <= max)
+        .collect();
+    if jobs.last() != Some(&max) {
+        jobs.push(max);
+    }
+    jobs
+}
+
+/// Yields the next number of jobs to profile based on the results so far.
+pub struct Sequencer {
+    strategy: Strategy,
+    queue: Vec<u32>,
+    visited: Vec<u32>,
+}
+
+impl Sequencer {
+    pub fn next(&mut self, summaries: &[JobsSummary]) -> Option<u32> {
+        let next = if self.queue.is_empty() {
+            match self.strategy {
+                Strategy::Fixed(_) => None,
+                Strategy::Adaptive { tolerance, .. } => bisect(summaries, tolerance, &self.visited),
+            }
+        } else {
+            Some(self.queue.remove(0))
+        };
+
+        if let Some(jobs) = next {
+            self.visited.push(jobs);
+        }
+        next
+    }
+
+    pub fn info(&self) -> SequenceInfo {
+        SequenceInfo {
+            strategy: self.strategy.describe(),
+            visited: self.visited.clone(),
+        }
+    }
+}
+
+/// Bisects the gap below the lowest number of jobs whose elapsed time is
+/// within the knee tolerance of the best one, until it is narrower than
+/// `tolerance`. Failed runs leave no summary, so a `visited` number of jobs
+/// inside the gap is replaced by the closest one not tried yet.
+fn bisect(summaries: &[JobsSummary], tolerance: u32, visited: &[u32]) -> Option<u32> {
+    let mut measured: Vec<(u32, f64)> = summaries
+        .iter()
+        .map(|s| (s.jobs, s.elapsed_time.mean))
+        .collect();
+    measured.sort_by_key(|(jobs, _)| *jobs);
+
+    let best = measured
+        .iter()
+        .map(|(_, elapsed)| *elapsed)
+        .min_by(|a, b| a.total_cmp(b))?;
+    let knee = measured
+        .iter()
+        .position(|(_, elapsed)| *elapsed <= best * (1.0 + KNEE_TOLERANCE))?;
+    if knee == 0 {
+        return None;
+    }
+
+    let (low, high) = (measured[knee - 1].0, measured[knee].0);
+    if high - low <= tolerance {
+        return None;
+    }
+    let middle = low + (high - low) / 2;
+    (low + 1..high)
+        .filter(|jobs| !visited.contains(jobs))
+        .min_by_key(|jobs| jobs.abs_diff(middle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Statistics;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Strategy::parse(Some("4"), false, 8).unwrap(),
+            Strategy::Fixed(vec![4])
+        );
+        assert_eq!(
+            Strategy::parse(Some("3"), true, 8).unwrap(),
+            Strategy::Fixed(vec![1, 2, 3])
+        );
+        assert_eq!(
+            Strategy::parse(Some("1,2,8..16:step=4"), false, 8).unwrap(),
+            Strategy::Fixed(vec![1, 2, 8, 12, 16])
+        );
+        assert_eq!(
+            Strategy::parse(Some("pow2"), false, 12).unwrap(),
+            Strategy::Fixed(vec![1, 2, 4, 8, 12])
+        );
+        assert_eq!(
+            Strategy::parse(Some("adaptive:tol=2"), false, 12).unwrap(),
+            Strategy::Adaptive {
+                max: 12,
+                tolerance: 2
+            }
+        );
+        assert_eq!(
+            Strategy::parse(None, true, 3).unwrap(),
+            Strategy::Fixed(vec![1, 2, 3])
+        );
+        assert!(Strategy::parse(Some("0"), false, 8).is_err());
+        assert!(Strategy::parse(Some("8..4"), false, 8).is_err());
+        assert!(Strategy::parse(Some("adaptive:x"), false, 8).is_err());
+    }
+
+    fn summary(jobs: u32, elapsed: f64) -> JobsSummary {
+        let stats = Statistics::from_samples(&[elapsed]).unwrap();
+        JobsSummary {
+            jobs,
+            runs: 1,
+            elapsed_time: stats,
+            user_time: stats,
+            system_time: stats,
+            peak_memory: 0,
+            stall: None,
+            memory: None,
+        }
+    }
+
+    #[test]
+    fn test_adaptive() {
+        // elapsed time stops improving at 6 jobs
+        let elapsed = |jobs: u32| 60.0 / jobs.min(6) as f64;
+        let mut sequencer = Strategy::Adaptive {
+            max: 16,
+            tolerance: 1,
+        }
+        .sequencer();
+        let mut summaries = Vec::new();
+
+        while let Some(jobs) = sequencer.next(&summaries) {
+            summaries.push(summary(jobs, elapsed(jobs)));
+        }
+
+        assert_eq!(sequencer.info().visited, vec![1, 2, 4, 8, 16, 6, 5]);
+    }
+
+    #[test]
+    fn test_adaptive_failed_midpoint() {
+        // builds with 6 jobs fail and leave no summary
+        let elapsed = |jobs: u32| 60.0 / jobs.min(6) as f64;
+        let mut sequencer = Strategy::Adaptive {
+            max: 16,
+            tolerance: 1,
+        }
+        .sequencer();
+        let mut summaries = Vec::new();
+
+        while let Some(jobs) = sequencer.next(&summaries) {
+            assert!(sequencer.info().visited.len() < 16);
+            if jobs != 6 {
+                summaries.push(summary(jobs, elapsed(jobs)));
+            }
+        }
+
+        assert_eq!(sequencer.info().visited, vec![1, 2, 4, 8, 16, 6, 5, 7]);
+    }
+}
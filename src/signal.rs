@@ -0,0 +1,219 @@
+//! SIGINT and SIGTERM handling: the first signal is recorded and forwarded to
+//! the process group of the running build so bsprof can save what it
+//! collected, a second one kills the group right away. The same watch
+//! terminates builds running past their deadline.
+
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// Time the build gets to exit after the forwarded signal or SIGTERM before
+/// SIGKILL.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// Process group being watched, 0 when none.
+static WATCHED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle(signal: libc::c_int) {
+    let first = RECEIVED
+        .compare_exchange(0, signal, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok();
+    // kill is async-signal-safe, the watch thread may be waiting for the
+    // grace period
+    let pgid = WATCHED.load(Ordering::SeqCst);
+    if !first && pgid > 0 {
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+}
+
+/// Installs the handlers, they stay installed so that a second signal kills
+/// the build instead of bsprof.
+pub fn install() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// The first SIGINT or SIGTERM received, if any.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Sends `signal` to every process of the group led by `pgid`.
+pub fn kill_group(pgid: u32, signal: i32) {
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), signal);
+    }
+}
+
+/// Why a watched process group was stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    Signal(i32),
+    Timeout,
+}
+
+/// Watches for signals and the deadline while a build spawned in its own
+/// process group runs.
+pub struct GroupWatch {
+    tx: Sender<()>,
+    thread: std::thread::JoinHandle<Option<Stop>>,
+}
+
+impl GroupWatch {
+    pub fn start(pgid: u32, deadline: Option<Instant>) -> GroupWatch {
+        WATCHED.store(pgid as i32, Ordering::SeqCst);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || watch(pgid, deadline, rx));
+        GroupWatch { tx, thread }
+    }
+
+    /// Stops watching once the group leader was reaped, returning why the
+    /// build was stopped. Processes left in a stopped group are killed.
+    pub fn stop(self, pgid: u32) -> Option<Stop> {
+        let _ = self.tx.send(());
+        let stop = self.thread.join().unwrap();
+        WATCHED.store(0, Ordering::SeqCst);
+        if stop.is_some() {
+            kill_group(pgid, libc::SIGKILL);
+        }
+        stop
+    }
+}
+
+fn watch(pgid: u32, deadline: Option<Instant>, rx: Receiver<()>) -> Option<Stop> {
+    loop {
+        let poll = deadline.map_or(POLL_INTERVAL, |deadline| {
+            POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now()))
+        });
+        match rx.recv_timeout(poll) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => return None,
+        }
+        let stop = match received() {
+            Some(signal) => Stop::Signal(signal),
+            None if deadline.is_some_and(|deadline| Instant::now() >= deadline) => Stop::Timeout,
+            None => continue,
+        };
+        kill_group(
+            pgid,
+            match stop {
+                Stop::Signal(signal) => signal,
+                Stop::Timeout => libc::SIGTERM,
+            },
+        );
+        if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(GRACE_PERIOD) {
+            println!(
+                "Processes did not exit within {} s of the signal, killing them",
+                GRACE_PERIOD.as_secs()
+            );
+            kill_group(pgid, libc::SIGKILL);
+        }
+        return Some(stop);
+    }
+}
+
+/// Serializes the tests depending on the received signal.
+#[cfg(test)]
+pub static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{Command, SpawnOptions};
+
+    /// A process of the group is alive, zombies left to an init which does
+    /// not reap them are not counted.
+    fn group_exists(pgid: u32) -> bool {
+        // the group may take a moment to die after SIGKILL
+        std::thread::sleep(Duration::from_millis(100));
+        std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("stat")).ok())
+            .any(|stat| {
+                let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 1..]
+                    .split_whitespace()
+                    .collect();
+                fields[0] != "Z" && fields[2] == pgid.to_string()
+            })
+    }
+
+    fn spawn_group(script: &str) -> std::process::Child {
+        let args = vec!["-c".to_string(), script.to_string()];
+        Command::new(&"sh".to_string(), &args)
+            .spawn_with(&SpawnOptions {
+                process_group: true,
+                ..Default::default()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_signal_forwarded() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut child = spawn_group("sleep 30 & sleep 30");
+        let pgid = child.id();
+        let watch = GroupWatch::start(pgid, None);
+
+        RECEIVED.store(libc::SIGTERM, Ordering::SeqCst);
+        child.wait().unwrap();
+        let stop = watch.stop(pgid);
+        RECEIVED.store(0, Ordering::SeqCst);
+
+        assert_eq!(stop, Some(Stop::Signal(libc::SIGTERM)));
+        assert!(!group_exists(pgid));
+    }
+
+    #[test]
+    fn test_second_signal() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // the ignored SIGTERM is inherited by the sleeps
+        let mut child = spawn_group("trap '' TERM; sleep 30 & sleep 30");
+        let pgid = child.id();
+        std::thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        let watch = GroupWatch::start(pgid, None);
+
+        handle(libc::SIGTERM);
+        std::thread::sleep(Duration::from_millis(100));
+        handle(libc::SIGTERM);
+        child.wait().unwrap();
+        let stop = watch.stop(pgid);
+        RECEIVED.store(0, Ordering::SeqCst);
+
+        assert_eq!(stop, Some(Stop::Signal(libc::SIGTERM)));
+        assert!(start.elapsed() < GRACE_PERIOD);
+        assert!(!group_exists(pgid));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut child = spawn_group("sleep 30 & sleep 30");
+        let pgid = child.id();
+        let start = Instant::now();
+        let watch = GroupWatch::start(pgid, Some(start + Duration::from_millis(200)));
+
+        child.wait().unwrap();
+        let stop = watch.stop(pgid);
+
+        assert_eq!(stop, Some(Stop::Timeout));
+        assert!(start.elapsed() < GRACE_PERIOD);
+        assert!(!group_exists(pgid));
+    }
+}
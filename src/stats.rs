@@ -0,0 +1,91 @@
+//! Descriptive statistics over repeated measurements.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Statistics {
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub ci95_low: f64,
+    pub ci95_high: f64,
+}
+
+// two-sided 95% Student's t critical values for 1..=30 degrees of freedom
+const T_CRITICAL_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+const Z_CRITICAL_95: f64 = 1.960;
+
+impl Statistics {
+    pub fn from_samples(samples: &[f64]) -> Option<Statistics> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let n = samples.len();
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        // sample standard deviation
+        let stddev = if n > 1 {
+            (sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let critical = T_CRITICAL_95.get(n.saturating_sub(2)).copied();
+        let margin = if n > 1 {
+            critical.unwrap_or(Z_CRITICAL_95) * stddev / (n as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Statistics {
+            mean,
+            median,
+            stddev,
+            min: sorted[0],
+            max: sorted[n - 1],
+            ci95_low: mean - margin,
+            ci95_high: mean + margin,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_samples() {
+        let stats = Statistics::from_samples(&[4.0, 2.0, 6.0, 8.0]).unwrap();
+
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.median, 5.0);
+        assert_eq!(stats.min, 2.0);
+        assert_eq!(stats.max, 8.0);
+        assert!((stats.stddev - 2.581989).abs() < 1e-6);
+        // t(3) = 3.182
+        assert!((stats.ci95_high - 5.0 - 3.182 * stats.stddev / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_single_sample() {
+        let stats = Statistics::from_samples(&[3.0]).unwrap();
+
+        assert_eq!(stats.stddev, 0.0);
+        assert_eq!(stats.ci95_low, 3.0);
+        assert_eq!(stats.ci95_high, 3.0);
+        assert!(Statistics::from_samples(&[]).is_none());
+    }
+}
@@ -119,12 +119,11 @@ def main(args):
         if jobs not in charts:
             continue
 
-        times = np.array([d["elapsed"] for d in profile_data["datapoints"]])
-        cpu_usage = np.array([d["cpu_usage"] for d in profile_data["datapoints"]])
-        memory_usage = np.array([d["memory_usage"] for d in profile_data["datapoints"]])
-        cpus_utilization = np.array(
-            [d["cpus_utilization"] for d in profile_data["datapoints"]]
-        )
+        datapoints = [d for d in profile_data["datapoints"] if "cpu" in d and "mem" in d]
+        times = np.array([d["elapsed"] for d in datapoints])
+        cpu_usage = np.array([d["cpu"]["usage"] for d in datapoints])
+        memory_usage = np.array([d["mem"]["used"] for d in datapoints])
+        cpus_utilization = np.array([d["cpu"]["cores"] for d in datapoints])
         cpu_cores_data = list(map(np.array, zip(*cpus_utilization)))
         memory_percent_usage = (
             memory_usage / report["system_info"]["total_memory"] * 100